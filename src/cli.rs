@@ -1,15 +1,19 @@
 pub mod base64_options;
 pub mod csv_options;
+pub mod fake_options;
 pub mod gen_pass_options;
 pub mod http_options;
+pub mod pass_options;
 pub mod text_options;
 
 use anyhow::Result;
 use base64_options::Base64Command;
 use clap::{Parser, Subcommand};
 use csv_options::CsvOptions;
+use fake_options::FakeOptions;
 use gen_pass_options::GenPassOptions;
 use http_options::HttpCommand;
+use pass_options::PassCommand;
 use std::path::{Path, PathBuf};
 use text_options::TextCommand;
 
@@ -24,10 +28,16 @@ pub struct Cli {
 pub enum Commands {
     /// Show CSV or Convert CSV to JSON tools
     #[command(name = "csv")]
-    Csv(CsvOptions),
+    Csv(Box<CsvOptions>),
+    /// Generate fake records from a schema
+    #[command(name = "fake")]
+    Fake(FakeOptions),
     /// Generate Password
     #[command(name = "gen-pass")]
     GenPass(GenPassOptions),
+    /// Check password strength
+    #[command(subcommand)]
+    Pass(PassCommand),
     /// Encode or Decode Base64
     #[command(subcommand)]
     Base64(Base64Command),
@@ -58,6 +68,16 @@ fn verify_path(path: &str) -> Result<PathBuf> {
     }
 }
 
+fn verify_score(score: &str) -> Result<u8> {
+    match score.parse::<u8>() {
+        Ok(score) if score <= 4 => Ok(score),
+        _ => Err(anyhow::format_err!(
+            "Score must be between 0 and 4: {}",
+            score
+        )),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
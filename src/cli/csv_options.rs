@@ -1,36 +1,705 @@
-use clap::Args;
+use clap::{ArgAction, ArgGroup, Args, Subcommand};
+use encoding_rs::Encoding;
+use serde::Deserialize;
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 use super::verify_file;
 
 #[derive(Args, Debug)]
+#[command(args_conflicts_with_subcommands = true)]
 pub struct CsvOptions {
+    #[command(subcommand)]
+    pub command: Option<CsvCommand>,
+    /// Input file path
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    /// Input format [default: from the input file extension, csv otherwise]
+    #[arg(long, value_parser = parse_input_format)]
+    pub input_format: Option<InputFormat>,
+    /// Worksheet to read from an xlsx or ods workbook [default: the first sheet]
+    #[arg(long)]
+    pub sheet: Option<String>,
+    /// Input encoding, e.g. `gbk`, `latin1`, `utf-16le` [default: detected]
+    #[arg(long, value_parser = parse_encoding)]
+    pub encoding: Option<&'static Encoding>,
+    /// Output file path, `-` for stdout [default: output.<format>, stdout for table]
+    #[arg(short, long)]
+    pub output: Option<String>,
+    /// Output encoding
+    #[arg(long, value_parser = parse_encoding, default_value = "utf-8")]
+    pub output_encoding: &'static Encoding,
+    /// Whether the first row is a header, column names are synthesized if not
+    #[arg(long, default_value_t = true, action = ArgAction::Set)]
+    pub header: bool,
+    /// Delimiter
+    #[arg(short, long, value_parser = verify_delimiter, default_value_t = ',')]
+    pub delimiter: char,
+    /// Output format
+    #[arg(short, long, value_parser = parse_output_format, default_value = "json")]
+    pub format: OutputFormat,
+    /// How arrays are flattened when writing CSV
+    #[arg(long, value_parser = parse_array_mode, default_value = "index")]
+    pub arrays: ArrayMode,
+    /// Build nested objects and arrays from column names like `address.city` and `tags[0]`
+    #[arg(long, default_value_t = false)]
+    pub nested: bool,
+    /// Parquet compression codec
+    #[arg(long, value_parser = parse_compression, default_value = "snappy")]
+    pub compression: Compression,
+    /// Buffer all records to union their columns for csv and table output, always on for JSON, YAML and NDJSON input, CSV input otherwise streams with the columns of the first record
+    #[arg(long, default_value_t = false)]
+    pub union_columns: bool,
+    /// Infer column types from a sample of rows and emit numbers, booleans and nulls
+    #[arg(long, default_value_t = false)]
+    pub infer: bool,
+    /// Number of CSV rows sampled when inferring column types
+    #[arg(long, default_value_t = 1000)]
+    pub sample_size: usize,
+    /// CSV column type overrides, e.g. `age=int,active=bool`
+    #[arg(long, value_delimiter = ',', value_parser = parse_column_type_override)]
+    pub types: Vec<(String, ColumnType)>,
+    /// Replace a column with a computed value, e.g. `Name=upper(Name)`, can be repeated
+    #[arg(long)]
+    pub map: Vec<String>,
+    /// Append a computed column, e.g. `BMI=Weight/(Height*Height)`, can be repeated
+    #[arg(long)]
+    pub add: Vec<String>,
+    /// Columns to output, in order, optionally renamed with `alias=column`
+    #[arg(long, value_delimiter = ',')]
+    pub select: Vec<String>,
+    /// Only output records matching the expression, e.g. `Position == "Goalkeeper" && Age > 30`
+    #[arg(long = "where")]
+    pub filter: Option<String>,
+    /// Sort keys as `column[:asc|desc][:num|str]`
+    #[arg(long, value_delimiter = ',')]
+    pub sort_by: Vec<String>,
+    /// Number of records to skip
+    #[arg(long, default_value_t = 0)]
+    pub offset: usize,
+    /// Maximum number of records to output
+    #[arg(long)]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum CsvCommand {
+    /// Run a SQL query over one or more CSV files
+    #[command(name = "query")]
+    Query(CsvQueryOptions),
+    /// Profile each column: type, empty and distinct counts, numeric summary and top values
+    #[command(name = "stats")]
+    Stats(CsvStatsOptions),
+    /// Check every row against a YAML schema, exits non-zero on violations
+    #[command(name = "validate")]
+    Validate(CsvValidateOptions),
+    /// Compare two CSV snapshots keyed by one or more columns
+    #[command(name = "diff")]
+    Diff(CsvDiffOptions),
+    /// Join two CSV files on key columns
+    #[command(name = "join")]
+    Join(CsvJoinOptions),
+    /// Concatenate CSV files, reconciling their headers
+    #[command(name = "cat")]
+    Cat(CsvCatOptions),
+    /// Remove duplicate rows, by full row or key columns
+    #[command(name = "dedup")]
+    Dedup(CsvDedupOptions),
+    /// Split a CSV into multiple files by rows, size or column value
+    #[command(name = "split")]
+    Split(CsvSplitOptions),
+    /// Randomly sample rows with reservoir sampling, optionally per value of a column
+    #[command(name = "sample")]
+    Sample(CsvSampleOptions),
+    /// Shuffle the rows, reproducibly with `--seed`
+    #[command(name = "shuffle")]
+    Shuffle(CsvShuffleOptions),
+    /// Infer a JSON Schema, Rust struct or TypeScript interface from the columns
+    #[command(name = "schema")]
+    Schema(CsvSchemaOptions),
+}
+
+#[derive(Args, Debug)]
+pub struct CsvQueryOptions {
+    /// SQL query, e.g. `SELECT Nationality, count(*) FROM players GROUP BY Nationality`,
+    /// booleans are stored as 0 and 1 and repeated result columns are renamed `Name_2`
+    pub sql: String,
+    /// Tables as `name=path`, a bare path is named after its file stem
+    #[arg(short, long = "table", required = true, value_parser = parse_table)]
+    pub tables: Vec<(String, String)>,
+    /// Delimiter of the input files
+    #[arg(short, long, value_parser = verify_delimiter, default_value_t = ',')]
+    pub delimiter: char,
+    /// Output file path, `-` for stdout
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+    /// Output format
+    #[arg(short, long, value_parser = parse_output_format, default_value = "table")]
+    pub format: OutputFormat,
+}
+
+#[derive(Args, Debug)]
+pub struct CsvStatsOptions {
     /// Input CSV file path
     #[arg(short, long, value_parser = verify_file, default_value = "-")]
     pub input: String,
-    /// Output JSON file path
-    #[arg(short, long, default_value = "output.json")]
+    /// Whether the first row is a header, column names are synthesized if not
+    #[arg(long, default_value_t = true, action = ArgAction::Set)]
+    pub header: bool,
+    /// Delimiter
+    #[arg(short, long, value_parser = verify_delimiter, default_value_t = ',')]
+    pub delimiter: char,
+    /// How distinct values are counted, `hll` estimates them, the top values and the median in constant memory
+    #[arg(long, value_parser = parse_distinct_mode, default_value = "exact")]
+    pub distinct: DistinctMode,
+    /// Number of most frequent values reported per column
+    #[arg(long, default_value_t = 5)]
+    pub top: usize,
+    /// Output file path, `-` for stdout
+    #[arg(short, long, default_value = "-")]
     pub output: String,
-    /// Show header
-    #[arg(long, default_value_t = true)]
+    /// Output format
+    #[arg(short, long, value_parser = parse_output_format, default_value = "table")]
+    pub format: OutputFormat,
+}
+
+#[derive(Args, Debug)]
+pub struct CsvValidateOptions {
+    /// Input CSV file path
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    /// Schema file describing the columns
+    #[arg(short, long, value_parser = verify_file)]
+    pub schema: String,
+    /// Whether the first row is a header, column names are synthesized if not
+    #[arg(long, default_value_t = true, action = ArgAction::Set)]
     pub header: bool,
     /// Delimiter
-    #[arg(short, long, default_value_t = ',')]
+    #[arg(short, long, value_parser = verify_delimiter, default_value_t = ',')]
     pub delimiter: char,
+    /// Output file path for the violation report, `-` for stdout
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+    /// Output format of the violation report
+    #[arg(short, long, value_parser = parse_output_format, default_value = "table")]
+    pub format: OutputFormat,
+}
+
+#[derive(Args, Debug)]
+pub struct CsvDiffOptions {
+    /// Old CSV file path
+    #[arg(value_parser = verify_file)]
+    pub old: String,
+    /// New CSV file path
+    #[arg(value_parser = verify_file)]
+    pub new: String,
+    /// Key columns identifying a row
+    #[arg(short, long, required = true, value_delimiter = ',')]
+    pub key: Vec<String>,
+    /// Delimiter
+    #[arg(short, long, value_parser = verify_delimiter, default_value_t = ',')]
+    pub delimiter: char,
+    /// Output one full row per added, removed or changed record with an `_op` column
+    #[arg(long, default_value_t = false)]
+    pub patch: bool,
+    /// Output file path, `-` for stdout
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
     /// Output format
-    #[arg(short, long, value_parser = parse_output_format, default_value = "json")]
+    #[arg(short, long, value_parser = parse_output_format, default_value = "table")]
+    pub format: OutputFormat,
+}
+
+#[derive(Args, Debug)]
+pub struct CsvJoinOptions {
+    /// Left CSV file path
+    #[arg(value_parser = verify_file)]
+    pub left: String,
+    /// Right CSV file path
+    #[arg(value_parser = verify_file)]
+    pub right: String,
+    /// Key columns, `column` or `left_column=right_column`
+    #[arg(long, required = true, value_delimiter = ',')]
+    pub on: Vec<String>,
+    /// Join kind
+    #[arg(long, value_parser = parse_join_kind, default_value = "inner")]
+    pub how: JoinKind,
+    /// Delimiter
+    #[arg(short, long, value_parser = verify_delimiter, default_value_t = ',')]
+    pub delimiter: char,
+    /// Output file path, `-` for stdout
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+    /// Output format
+    #[arg(short, long, value_parser = parse_output_format, default_value = "csv")]
+    pub format: OutputFormat,
+}
+
+#[derive(Args, Debug)]
+pub struct CsvCatOptions {
+    /// Input CSV file paths
+    #[arg(required = true, value_parser = verify_file)]
+    pub inputs: Vec<String>,
+    /// Add a column with the path each row came from
+    #[arg(long)]
+    pub source_column: Option<String>,
+    /// Delimiter
+    #[arg(short, long, value_parser = verify_delimiter, default_value_t = ',')]
+    pub delimiter: char,
+    /// Output file path, `-` for stdout
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+    /// Output format
+    #[arg(short, long, value_parser = parse_output_format, default_value = "csv")]
+    pub format: OutputFormat,
+}
+
+#[derive(Args, Debug)]
+pub struct CsvDedupOptions {
+    /// Input CSV file path
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    /// Key columns, the full row is compared if none are given
+    #[arg(short, long, value_delimiter = ',')]
+    pub key: Vec<String>,
+    /// Which of the duplicates to keep
+    #[arg(long, value_parser = parse_keep, default_value = "first")]
+    pub keep: Keep,
+    /// Delimiter
+    #[arg(short, long, value_parser = verify_delimiter, default_value_t = ',')]
+    pub delimiter: char,
+    /// Output file path, `-` for stdout
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+    /// Output format
+    #[arg(short, long, value_parser = parse_output_format, default_value = "csv")]
+    pub format: OutputFormat,
+}
+
+#[derive(Args, Debug)]
+#[command(group(ArgGroup::new("by").required(true).args(["rows", "size", "column"])))]
+pub struct CsvSplitOptions {
+    /// Input CSV file path
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    /// Maximum number of rows per file
+    #[arg(long)]
+    pub rows: Option<usize>,
+    /// Approximate maximum size per file, e.g. `500K`, `10M`
+    #[arg(long, value_parser = parse_size)]
+    pub size: Option<u64>,
+    /// Write one file per distinct value of this column
+    #[arg(long)]
+    pub column: Option<String>,
+    /// Directory the files are written to, created if missing
+    #[arg(long, default_value = ".")]
+    pub output_dir: PathBuf,
+    /// File name prefix [default: input file stem, or `split` for stdin]
+    #[arg(long)]
+    pub prefix: Option<String>,
+    /// Delimiter
+    #[arg(short, long, value_parser = verify_delimiter, default_value_t = ',')]
+    pub delimiter: char,
+}
+
+#[derive(Args, Debug)]
+pub struct CsvSampleOptions {
+    /// Input CSV file path
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    /// Number of rows to keep, per distinct value when `--by` is given
+    #[arg(short, long)]
+    pub n: usize,
+    /// Seed for a reproducible sample [default: random]
+    #[arg(long)]
+    pub seed: Option<u64>,
+    /// Sample each distinct value of this column separately
+    #[arg(long)]
+    pub by: Option<String>,
+    /// Delimiter
+    #[arg(short, long, value_parser = verify_delimiter, default_value_t = ',')]
+    pub delimiter: char,
+    /// Output file path, `-` for stdout
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+    /// Output format
+    #[arg(short, long, value_parser = parse_output_format, default_value = "csv")]
+    pub format: OutputFormat,
+}
+
+#[derive(Args, Debug)]
+pub struct CsvShuffleOptions {
+    /// Input CSV file path
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    /// Seed for a reproducible order [default: random]
+    #[arg(long)]
+    pub seed: Option<u64>,
+    /// Delimiter
+    #[arg(short, long, value_parser = verify_delimiter, default_value_t = ',')]
+    pub delimiter: char,
+    /// Output file path, `-` for stdout
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+    /// Output format
+    #[arg(short, long, value_parser = parse_output_format, default_value = "csv")]
     pub format: OutputFormat,
 }
 
+#[derive(Args, Debug)]
+pub struct CsvSchemaOptions {
+    /// Input CSV file path
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    /// Whether the first row is a header, column names are synthesized if not
+    #[arg(long, default_value_t = true, action = ArgAction::Set)]
+    pub header: bool,
+    /// Delimiter
+    #[arg(short, long, value_parser = verify_delimiter, default_value_t = ',')]
+    pub delimiter: char,
+    /// Number of rows sampled when inferring column types
+    #[arg(long, default_value_t = 1000)]
+    pub sample_size: usize,
+    /// What to generate: json-schema, rust or typescript
+    #[arg(long, value_parser = parse_schema_emit, default_value = "json-schema")]
+    pub emit: SchemaEmit,
+    /// Name of the struct or interface, must be a valid identifier [default: from the input file name]
+    #[arg(long)]
+    pub name: Option<String>,
+    /// Output file path, `-` for stdout
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchemaEmit {
+    JsonSchema,
+    Rust,
+    TypeScript,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinKind {
+    Inner,
+    Left,
+    Outer,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Keep {
+    First,
+    Last,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum DistinctMode {
+    Exact,
+    /// HyperLogLog estimate
+    Hll,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum InputFormat {
+    Csv,
+    Json,
+    Yaml,
+    /// newline delimited JSON, one object per line
+    Ndjson,
+    /// xlsx, xls or ods workbook
+    Excel,
+}
+
+impl InputFormat {
+    /// guess the format from a file extension, stdin and unknown extensions are CSV
+    pub fn from_path(path: &str) -> Self {
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default();
+        match extension.to_lowercase().as_str() {
+            "json" => InputFormat::Json,
+            "yaml" | "yml" => InputFormat::Yaml,
+            "ndjson" | "jsonl" => InputFormat::Ndjson,
+            "xlsx" | "xlsm" | "xlsb" | "xls" | "ods" => InputFormat::Excel,
+            _ => InputFormat::Csv,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum OutputFormat {
     Json,
     Yaml,
+    Csv,
+    /// newline delimited JSON, one record per line
+    Ndjson,
+    /// TOML array of tables
+    Toml,
+    /// aligned, human readable table
+    Table,
+    /// Excel workbook with a single sheet
+    Xlsx,
+    /// Apache Parquet, columns typed from the records
+    Parquet,
+    /// Arrow IPC file
+    Arrow,
+}
+
+impl OutputFormat {
+    /// where the output goes when no path was given
+    pub fn default_output(&self) -> &'static str {
+        match self {
+            OutputFormat::Json => "output.json",
+            OutputFormat::Yaml => "output.yaml",
+            OutputFormat::Csv => "output.csv",
+            OutputFormat::Ndjson => "output.ndjson",
+            OutputFormat::Toml => "output.toml",
+            OutputFormat::Table => "-",
+            OutputFormat::Xlsx => "output.xlsx",
+            OutputFormat::Parquet => "output.parquet",
+            OutputFormat::Arrow => "output.arrow",
+        }
+    }
+
+    /// file extension used when a command writes several files
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Json => "json",
+            OutputFormat::Yaml => "yaml",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Ndjson => "ndjson",
+            OutputFormat::Toml => "toml",
+            OutputFormat::Table => "txt",
+            OutputFormat::Xlsx => "xlsx",
+            OutputFormat::Parquet => "parquet",
+            OutputFormat::Arrow => "arrow",
+        }
+    }
+}
+
+/// how arrays nested in records become CSV columns
+#[derive(Debug, Clone, Copy, Default)]
+pub enum ArrayMode {
+    /// one column per element, e.g. `tags[0]`, `tags[1]`
+    #[default]
+    Index,
+    /// a single column with the elements joined by `;`
+    Join,
+    /// a single column holding the array as JSON
+    Json,
+}
+
+/// codecs for Parquet output
+#[derive(Debug, Clone, Copy, Default)]
+pub enum Compression {
+    None,
+    #[default]
+    Snappy,
+    Gzip,
+    Zstd,
+    Lz4,
+}
+
+fn parse_input_format(format: &str) -> Result<InputFormat, anyhow::Error> {
+    match format.to_lowercase().as_str() {
+        "csv" => Ok(InputFormat::Csv),
+        "json" => Ok(InputFormat::Json),
+        "yaml" => Ok(InputFormat::Yaml),
+        "ndjson" => Ok(InputFormat::Ndjson),
+        "excel" | "xlsx" | "xls" | "ods" => Ok(InputFormat::Excel),
+        _ => Err(anyhow::format_err!("Invalid input format: {}", format)),
+    }
 }
 
-fn parse_output_format(format: &str) -> Result<OutputFormat, anyhow::Error> {
+pub(super) fn parse_output_format(format: &str) -> Result<OutputFormat, anyhow::Error> {
     match format.to_lowercase().as_str() {
         "json" => Ok(OutputFormat::Json),
         "yaml" => Ok(OutputFormat::Yaml),
+        "csv" => Ok(OutputFormat::Csv),
+        "ndjson" => Ok(OutputFormat::Ndjson),
+        "toml" => Ok(OutputFormat::Toml),
+        "table" => Ok(OutputFormat::Table),
+        "xlsx" => Ok(OutputFormat::Xlsx),
+        "parquet" => Ok(OutputFormat::Parquet),
+        "arrow" | "ipc" => Ok(OutputFormat::Arrow),
         _ => Err(anyhow::format_err!("Invalid output format: {}", format)),
     }
 }
+
+fn parse_array_mode(mode: &str) -> Result<ArrayMode, anyhow::Error> {
+    match mode.to_lowercase().as_str() {
+        "index" => Ok(ArrayMode::Index),
+        "join" => Ok(ArrayMode::Join),
+        "json" => Ok(ArrayMode::Json),
+        _ => Err(anyhow::format_err!("Invalid array mode: {}", mode)),
+    }
+}
+
+fn parse_compression(compression: &str) -> Result<Compression, anyhow::Error> {
+    match compression.to_lowercase().as_str() {
+        "none" | "uncompressed" => Ok(Compression::None),
+        "snappy" => Ok(Compression::Snappy),
+        "gzip" => Ok(Compression::Gzip),
+        "zstd" => Ok(Compression::Zstd),
+        "lz4" => Ok(Compression::Lz4),
+        _ => Err(anyhow::format_err!("Invalid compression: {}", compression)),
+    }
+}
+
+fn parse_distinct_mode(mode: &str) -> Result<DistinctMode, anyhow::Error> {
+    match mode.to_lowercase().as_str() {
+        "exact" => Ok(DistinctMode::Exact),
+        "hll" => Ok(DistinctMode::Hll),
+        _ => Err(anyhow::format_err!("Invalid distinct mode: {}", mode)),
+    }
+}
+
+fn parse_schema_emit(emit: &str) -> Result<SchemaEmit, anyhow::Error> {
+    match emit.to_lowercase().as_str() {
+        "json-schema" | "jsonschema" => Ok(SchemaEmit::JsonSchema),
+        "rust" | "rs" => Ok(SchemaEmit::Rust),
+        "typescript" | "ts" => Ok(SchemaEmit::TypeScript),
+        _ => Err(anyhow::format_err!("Invalid schema output: {}", emit)),
+    }
+}
+
+fn parse_join_kind(kind: &str) -> Result<JoinKind, anyhow::Error> {
+    match kind.to_lowercase().as_str() {
+        "inner" => Ok(JoinKind::Inner),
+        "left" => Ok(JoinKind::Left),
+        "outer" | "full" => Ok(JoinKind::Outer),
+        _ => Err(anyhow::format_err!("Invalid join kind: {}", kind)),
+    }
+}
+
+fn parse_keep(keep: &str) -> Result<Keep, anyhow::Error> {
+    match keep.to_lowercase().as_str() {
+        "first" => Ok(Keep::First),
+        "last" => Ok(Keep::Last),
+        _ => Err(anyhow::format_err!("Invalid keep option: {}", keep)),
+    }
+}
+
+/// a WHATWG encoding label, `latin1` maps to windows-1252 and `utf-16` to little endian
+fn parse_encoding(label: &str) -> Result<&'static Encoding, anyhow::Error> {
+    Encoding::for_label(label.trim().as_bytes())
+        .ok_or_else(|| anyhow::format_err!("Invalid encoding: {}", label))
+}
+
+/// a byte count with an optional `K`, `M` or `G` suffix (powers of 1024)
+fn parse_size(size: &str) -> Result<u64, anyhow::Error> {
+    let upper = size.trim().to_uppercase();
+    let number = upper.trim_end_matches('B');
+    let (number, unit) = match number.chars().last() {
+        Some('K') => (&number[..number.len() - 1], 1 << 10),
+        Some('M') => (&number[..number.len() - 1], 1 << 20),
+        Some('G') => (&number[..number.len() - 1], 1 << 30),
+        _ => (number, 1),
+    };
+    let number: u64 = number
+        .trim()
+        .parse()
+        .map_err(|_| anyhow::format_err!("Invalid size: {}", size))?;
+    match number.checked_mul(unit) {
+        Some(0) => Err(anyhow::format_err!(
+            "Size must be greater than zero: {}",
+            size
+        )),
+        Some(bytes) => Ok(bytes),
+        None => Err(anyhow::format_err!("Size is too large: {}", size)),
+    }
+}
+
+fn parse_table(table: &str) -> Result<(String, String), anyhow::Error> {
+    let (name, path) = match table.split_once('=') {
+        Some((name, path)) => (name.to_string(), path),
+        None => {
+            let stem = Path::new(table)
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .ok_or_else(|| anyhow::format_err!("Cannot name table: {}", table))?;
+            (stem.to_string(), table)
+        }
+    };
+    Ok((name, verify_file(path)?))
+}
+
+fn verify_delimiter(delimiter: &str) -> Result<char, anyhow::Error> {
+    let mut chars = delimiter.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii() => Ok(c),
+        _ => Err(anyhow::format_err!(
+            "Delimiter must be a single ASCII character: {}",
+            delimiter
+        )),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColumnType {
+    #[serde(alias = "str")]
+    String,
+    #[serde(alias = "int")]
+    Integer,
+    #[serde(alias = "number")]
+    Float,
+    #[serde(alias = "bool")]
+    Boolean,
+}
+
+impl FromStr for ColumnType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "string" | "str" => Ok(ColumnType::String),
+            "integer" | "int" => Ok(ColumnType::Integer),
+            "float" | "number" => Ok(ColumnType::Float),
+            "boolean" | "bool" => Ok(ColumnType::Boolean),
+            _ => Err(anyhow::format_err!("Invalid column type: {}", s)),
+        }
+    }
+}
+
+impl From<ColumnType> for &'static str {
+    fn from(ty: ColumnType) -> Self {
+        match ty {
+            ColumnType::String => "string",
+            ColumnType::Integer => "integer",
+            ColumnType::Float => "float",
+            ColumnType::Boolean => "boolean",
+        }
+    }
+}
+
+impl fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&'static str>::into(*self))
+    }
+}
+
+fn parse_column_type_override(s: &str) -> Result<(String, ColumnType), anyhow::Error> {
+    let (name, ty) = s
+        .rsplit_once('=')
+        .ok_or_else(|| anyhow::format_err!("Invalid column type override: {}", s))?;
+    Ok((name.to_string(), ty.parse()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("512").unwrap(), 512);
+        assert_eq!(parse_size("10k").unwrap(), 10 * 1024);
+        assert_eq!(parse_size("2MB").unwrap(), 2 * 1024 * 1024);
+        assert!(parse_size("0").is_err());
+        assert!(parse_size("ten").is_err());
+        assert!(parse_size("18446744073709551615G").is_err());
+    }
+}
@@ -0,0 +1,25 @@
+use clap::Args;
+
+use super::{
+    csv_options::{parse_output_format, OutputFormat},
+    verify_file,
+};
+
+#[derive(Args, Debug)]
+pub struct FakeOptions {
+    /// Schema file listing the tables and columns to generate
+    #[arg(short, long, value_parser = verify_file)]
+    pub schema: String,
+    /// Rows per table, unless the table sets its own `rows`
+    #[arg(short, long, default_value_t = 100)]
+    pub rows: usize,
+    /// Seed for reproducible data [default: random]
+    #[arg(long)]
+    pub seed: Option<u64>,
+    /// Output file path, `-` for stdout, or a directory when the schema has several tables [default: stdout, `.` for several tables]
+    #[arg(short, long)]
+    pub output: Option<String>,
+    /// Output format
+    #[arg(short, long, value_parser = parse_output_format, default_value = "csv")]
+    pub format: OutputFormat,
+}
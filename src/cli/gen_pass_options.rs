@@ -1,4 +1,8 @@
 use clap::Args;
+use indexmap::IndexSet;
+use std::str::FromStr;
+
+use super::{verify_file, verify_score};
 
 #[derive(Args, Debug)]
 pub struct GenPassOptions {
@@ -17,4 +21,190 @@ pub struct GenPassOptions {
     /// Include symbols
     #[arg(long, default_value_t = false)]
     pub no_symbols: bool,
+    /// Minimum number of characters from each enabled class
+    #[arg(long, default_value_t = 1)]
+    pub min_each: usize,
+    /// Exclude look-alike characters: 0 O 1 l I
+    #[arg(long, default_value_t = false)]
+    pub no_ambiguous: bool,
+    /// Never repeat a character twice in a row
+    #[arg(long, default_value_t = false)]
+    pub no_repeat: bool,
+    /// Never put three consecutive characters in a row, like `abc` or `321`
+    #[arg(long, default_value_t = false)]
+    pub no_sequence: bool,
+    /// Custom alphabet instead of the four classes, with ranges, e.g. `A-Z0-9_`
+    #[arg(long, conflicts_with_all = ["no_uppercase", "no_lowercase", "no_numbers", "no_symbols"])]
+    pub charset: Option<Charset>,
+    /// Template such as `Cvcc-9999-ssss`: C/c consonant, V/v vowel, A/a letter, 9 digit, s symbol, `\` keeps the next character as is
+    #[arg(long, conflicts_with_all = ["length", "charset", "min_each"])]
+    pub pattern: Option<Pattern>,
+    /// Generate a passphrase of this many words from the EFF large wordlist instead
+    #[arg(long, conflicts_with_all = ["length", "charset", "pattern"])]
+    pub words: Option<usize>,
+    /// Separator between passphrase words
+    #[arg(long, default_value = "-")]
+    pub separator: String,
+    /// Capitalize each passphrase word
+    #[arg(long, default_value_t = false)]
+    pub capitalize: bool,
+    /// Append a random digit to one of the passphrase words
+    #[arg(long, default_value_t = false)]
+    pub digit: bool,
+    /// Append a random symbol to one of the passphrase words
+    #[arg(long, default_value_t = false)]
+    pub symbol: bool,
+    /// Regenerate until the zxcvbn score (0-4) is at least this
+    #[arg(long, value_parser = verify_score)]
+    pub min_score: Option<u8>,
+    /// Policy file the passwords must pass, lengths outside its range are moved into it
+    #[arg(long, value_parser = verify_file)]
+    pub policy: Option<String>,
+    /// Number of passwords to generate
+    #[arg(short = 'n', long, default_value_t = 1)]
+    pub count: usize,
+    /// Never print the same password twice
+    #[arg(long, default_value_t = false)]
+    pub unique: bool,
+    /// Output format: plain, json or csv, json and csv include the strength of each password
+    #[arg(short, long, value_parser = parse_pass_format, default_value = "plain")]
+    pub format: PassFormat,
+}
+
+impl Default for GenPassOptions {
+    fn default() -> Self {
+        Self {
+            length: 16,
+            no_uppercase: false,
+            no_lowercase: false,
+            no_numbers: false,
+            no_symbols: false,
+            min_each: 1,
+            no_ambiguous: false,
+            no_repeat: false,
+            no_sequence: false,
+            charset: None,
+            pattern: None,
+            words: None,
+            separator: "-".to_string(),
+            capitalize: false,
+            digit: false,
+            symbol: false,
+            min_score: None,
+            policy: None,
+            count: 1,
+            unique: false,
+            format: PassFormat::Plain,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PassFormat {
+    /// one password per line
+    Plain,
+    Json,
+    Csv,
+}
+
+fn parse_pass_format(format: &str) -> Result<PassFormat, anyhow::Error> {
+    match format.to_lowercase().as_str() {
+        "plain" | "text" => Ok(PassFormat::Plain),
+        "json" => Ok(PassFormat::Json),
+        "csv" => Ok(PassFormat::Csv),
+        _ => Err(anyhow::format_err!("Invalid output format: {}", format)),
+    }
+}
+
+/// a custom alphabet, each character once
+#[derive(Debug, Clone, PartialEq)]
+pub struct Charset(pub Vec<char>);
+
+/// `a-z` is a range, `\` takes the next character literally, a leading or trailing `-` is literal
+impl FromStr for Charset {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chars = s.chars().peekable();
+        let mut ret = IndexSet::new();
+        let mut add = |c: char| {
+            ret.insert(c);
+        };
+        while let Some(c) = chars.next() {
+            let start = match c {
+                '\\' => chars
+                    .next()
+                    .ok_or_else(|| anyhow::format_err!("Charset ends with an escape: {}", s))?,
+                c => c,
+            };
+            let mut rest = chars.clone();
+            match (rest.next(), rest.next()) {
+                (Some('-'), Some(end)) => {
+                    if start > end {
+                        return Err(anyhow::format_err!("Invalid range {}-{}", start, end));
+                    }
+                    (start..=end).for_each(&mut add);
+                    chars = rest;
+                }
+                _ => add(start),
+            }
+        }
+        if ret.is_empty() {
+            return Err(anyhow::format_err!("Charset is empty"));
+        }
+        Ok(Charset(ret.into_iter().collect()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PatternPart {
+    Literal(char),
+    /// `C` or `c`
+    Consonant {
+        upper: bool,
+    },
+    /// `V` or `v`
+    Vowel {
+        upper: bool,
+    },
+    /// `A` or `a`
+    Letter {
+        upper: bool,
+    },
+    /// `9`
+    Digit,
+    /// `s`
+    Symbol,
+}
+
+/// a password template, one part per generated character
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern(pub Vec<PatternPart>);
+
+impl FromStr for Pattern {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chars = s.chars();
+        let mut ret = Vec::new();
+        while let Some(c) = chars.next() {
+            let part =
+                match c {
+                    'C' | 'c' => PatternPart::Consonant { upper: c == 'C' },
+                    'V' | 'v' => PatternPart::Vowel { upper: c == 'V' },
+                    'A' | 'a' => PatternPart::Letter { upper: c == 'A' },
+                    '9' => PatternPart::Digit,
+                    's' => PatternPart::Symbol,
+                    '\\' => PatternPart::Literal(chars.next().ok_or_else(|| {
+                        anyhow::format_err!("Pattern ends with an escape: {}", s)
+                    })?),
+                    c => PatternPart::Literal(c),
+                };
+            ret.push(part);
+        }
+        if ret.is_empty() {
+            return Err(anyhow::format_err!("Pattern is empty"));
+        }
+        Ok(Pattern(ret))
+    }
 }
@@ -0,0 +1,19 @@
+use super::verify_file;
+use clap::{Args, Parser};
+
+#[derive(Parser, Debug)]
+pub enum PassCommand {
+    /// Score a password read from the first line of the input
+    #[command(name = "check")]
+    Check(PassCheckOptions),
+}
+
+#[derive(Args, Debug)]
+pub struct PassCheckOptions {
+    /// Input file path
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    /// Policy file to check the password against, exits non-zero on violations
+    #[arg(long, value_parser = verify_file)]
+    pub policy: Option<String>,
+}
@@ -2,15 +2,24 @@ use clap::Parser;
 use rcli::{
     cli::{
         base64_options::Base64Command,
+        csv_options::CsvCommand,
+        gen_pass_options::PassFormat,
         http_options::HttpCommand,
+        pass_options::PassCommand,
         text_options::{TextCommand, TextSignFormat},
         Cli, Commands,
     },
     process,
 };
 
-/// rcli csv -i input.csv -o output.json --header -d ','
+/// rcli csv -i input.csv -o output.json --header true -d ','
+/// rcli csv -i players.xlsx --sheet Squad -f csv -o players.csv
+/// rcli csv query "SELECT Nationality, count(*) FROM players GROUP BY Nationality" -t players=assets/juventus.csv
+/// rcli fake --schema fixtures/people.fake.yaml --rows 1000 --seed 42 -o data
 /// rcli gen-pass --length 16 --uppercase --lowercase --numbers --symbols
+/// rcli gen-pass --words 6 --separator - --capitalize --digit
+/// rcli gen-pass --count 100 --unique --format csv --policy fixtures/password.policy.yaml
+/// echo 'correct horse' | rcli pass check --policy fixtures/password.policy.yaml
 /// rcli base64 encode -i input.txt
 /// rcli base64 decode -i input.txt
 /// rcli text sign -k fixtures/blake3
@@ -19,13 +28,60 @@ async fn main() -> anyhow::Result<()> {
     tracing_subscriber::fmt::init();
     let cli = Cli::parse();
     match cli.command {
-        Commands::Csv(options) => {
-            process::process_csv(&options.input, &options.output, &options.format)?
+        Commands::Csv(options) => match &options.command {
+            Some(CsvCommand::Query(query)) => process::process_csv_query(query)?,
+            Some(CsvCommand::Stats(stats)) => process::process_csv_stats(stats)?,
+            Some(CsvCommand::Validate(validate)) => {
+                let violations = process::process_csv_validate(validate)?;
+                if violations > 0 {
+                    anyhow::bail!("{} violations found", violations);
+                }
+            }
+            Some(CsvCommand::Diff(diff)) => process::process_csv_diff(diff)?,
+            Some(CsvCommand::Join(join)) => process::process_csv_join(join)?,
+            Some(CsvCommand::Cat(cat)) => process::process_csv_cat(cat)?,
+            Some(CsvCommand::Dedup(dedup)) => process::process_csv_dedup(dedup)?,
+            Some(CsvCommand::Split(split)) => {
+                for path in process::process_csv_split(split)? {
+                    println!("{}", path.display());
+                }
+            }
+            Some(CsvCommand::Sample(sample)) => process::process_csv_sample(sample)?,
+            Some(CsvCommand::Shuffle(shuffle)) => process::process_csv_shuffle(shuffle)?,
+            Some(CsvCommand::Schema(schema)) => process::process_csv_schema(schema)?,
+            None => process::process_csv(&options)?,
+        },
+        Commands::Fake(options) => {
+            for path in process::process_fake(&options)? {
+                println!("{}", path.display());
+            }
         }
         Commands::GenPass(options) => {
-            let password = process::process_genpass(&options)?;
-            println!("{}", password);
+            let passwords = process::process_genpass_batch(&options)?;
+            match passwords.as_slice() {
+                [password] if options.format == PassFormat::Plain => {
+                    println!("{}", password);
+                    eprintln!("{}", process::process_strength(password));
+                }
+                passwords => print!(
+                    "{}",
+                    process::process_genpass_report(passwords, options.format)?
+                ),
+            }
         }
+        Commands::Pass(command) => match command {
+            PassCommand::Check(options) => {
+                let (strength, violations) =
+                    process::process_pass_check(&options.input, options.policy.as_deref())?;
+                println!("{}", strength);
+                for violation in &violations {
+                    println!("violation: {}", violation);
+                }
+                if !violations.is_empty() {
+                    anyhow::bail!("{} policy violations found", violations.len());
+                }
+            }
+        },
         Commands::Base64(command) => match command {
             Base64Command::Encode(options) => {
                 let encoded = process::process_encode(&options.input, &options.format)?;
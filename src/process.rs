@@ -1,14 +1,33 @@
 mod b64;
 mod csv;
+mod fake;
 mod gen_pass;
 mod http_serve;
+mod pass;
+mod policy;
 mod text;
 
 pub use b64::process_decode;
 pub use b64::process_encode;
 pub use csv::process_csv;
+pub use csv::process_csv_cat;
+pub use csv::process_csv_dedup;
+pub use csv::process_csv_diff;
+pub use csv::process_csv_join;
+pub use csv::process_csv_query;
+pub use csv::process_csv_sample;
+pub use csv::process_csv_schema;
+pub use csv::process_csv_shuffle;
+pub use csv::process_csv_split;
+pub use csv::process_csv_stats;
+pub use csv::process_csv_validate;
+pub use fake::process_fake;
 pub use gen_pass::process_genpass;
+pub use gen_pass::process_genpass_batch;
+pub use gen_pass::process_genpass_report;
 pub use http_serve::process_http_serve;
+pub use pass::process_pass_check;
+pub use pass::process_strength;
 pub use text::process_genkey;
 pub use text::process_sign;
 pub use text::process_verify;
@@ -1,30 +1,294 @@
-use csv::Reader;
-use serde_json::Value;
-use std::fs::File;
+mod cat;
+mod columnar;
+mod dedup;
+mod diff;
+mod encoding;
+mod expr;
+mod flatten;
+mod functions;
+mod infer;
+mod join;
+mod query;
+mod sample;
+mod schema;
+mod source;
+mod split;
+mod sql;
+mod stats;
+mod validate;
+mod workbook;
+pub(super) mod writer;
 
-use crate::cli::csv_options::OutputFormat;
+use anyhow::Result;
+use csv::{Reader, ReaderBuilder, StringRecord};
+use encoding_rs::UTF_8;
+use serde_json::{Map, Value};
+use std::io::Read;
 
-pub fn process_csv(input: &str, output: &str, format: &OutputFormat) -> anyhow::Result<()> {
-    let mut reader = Reader::from_path(input)?;
-    let mut ret = Vec::with_capacity(128);
+use crate::{
+    cli::csv_options::{ColumnType, CsvOptions, InputFormat, OutputFormat},
+    utils::{get_reader, get_writer},
+};
+use infer::ColumnTyping;
+use query::Query;
+use source::Records;
+use writer::WriteOptions;
 
-    let headers = reader.headers()?.clone();
-    for record in reader.records() {
-        let record = record?;
-        let json_value = headers.iter().zip(record.iter()).collect::<Value>();
-        ret.push(json_value);
+pub use cat::process_csv_cat;
+pub use dedup::process_csv_dedup;
+pub use diff::process_csv_diff;
+pub use join::process_csv_join;
+pub use sample::process_csv_sample;
+pub use sample::process_csv_shuffle;
+pub use schema::process_csv_schema;
+pub use split::process_csv_split;
+pub use sql::process_csv_query;
+pub use stats::process_csv_stats;
+pub use validate::process_csv_validate;
+
+pub fn process_csv(options: &CsvOptions) -> Result<()> {
+    let format = options
+        .input_format
+        .unwrap_or_else(|| InputFormat::from_path(&options.input));
+    let records = match format {
+        InputFormat::Csv => read_csv_records(options)?,
+        InputFormat::Excel => workbook::read_workbook(
+            get_reader(&options.input)?,
+            options.sheet.as_deref(),
+            options.header,
+        )?,
+        format => source::read_documents(
+            format,
+            encoding::decode_reader(get_reader(&options.input)?, options.encoding)?,
+        )?,
+    };
+    let records = Query::try_new(options)?.apply(records)?;
+    // nesting comes last so `--where` and `--select` still see the flat column names
+    let records: Records = if options.nested {
+        Box::new(records.map(|record| flatten::unflatten(record?)))
+    } else {
+        records
+    };
+
+    let output = options
+        .output
+        .as_deref()
+        .unwrap_or(options.format.default_output());
+    let binary = matches!(
+        options.format,
+        OutputFormat::Xlsx | OutputFormat::Parquet | OutputFormat::Arrow
+    );
+    if binary && options.output_encoding != UTF_8 {
+        return Err(anyhow::format_err!(
+            "--output-encoding does not apply to {:?} output",
+            options.format
+        ));
     }
+    let output = encoding::encode_writer(get_writer(output)?, options.output_encoding);
+    let write_options = WriteOptions {
+        // documents rarely share the same keys, CSV and workbook rows all have the header's
+        union_columns: options.union_columns
+            || matches!(
+                format,
+                InputFormat::Json | InputFormat::Yaml | InputFormat::Ndjson
+            ),
+        ..options.into()
+    };
+    let mut writer = writer::new_writer(options.format, output, write_options);
+    for record in records {
+        writer.write_record(&record?)?;
+    }
+    writer.finish()
+}
 
-    match format {
-        OutputFormat::Json => {
-            let file = File::create(output)?;
-            serde_json::to_writer_pretty(file, &ret)?;
-        }
-        OutputFormat::Yaml => {
-            let file = File::create(output)?;
-            serde_yaml::to_writer(file, &ret)?;
+/// stream CSV rows as JSON records, typed according to `--infer` and `--types`
+fn read_csv_records(options: &CsvOptions) -> Result<Records> {
+    let input = encoding::decode_reader(get_reader(&options.input)?, options.encoding)?;
+    let mut reader = ReaderBuilder::new()
+        .delimiter(options.delimiter as u8)
+        .has_headers(options.header)
+        .from_reader(input);
+    let headers = read_headers(&mut reader, options.header)?;
+    let mut records = reader.into_records();
+
+    // only rows needed for inference are buffered, the rest is streamed
+    let mut sample = Vec::new();
+    if options.infer {
+        for record in records.by_ref().take(options.sample_size) {
+            sample.push(record?);
         }
     }
+    let inferred = options
+        .infer
+        .then(|| infer::infer_types(&sample, headers.len()));
+    let typings = resolve_typings(&headers, inferred.as_deref(), &options.types)?;
+
+    let records = sample
+        .into_iter()
+        .map(Ok)
+        .chain(records)
+        .map(move |record| {
+            let record = record?;
+            to_json_record(&headers, &typings, &record)
+        });
+    Ok(Box::new(records))
+}
 
-    Ok(())
+/// build a csv reader over a file or stdin (`-`), transcoded to UTF-8 from its detected encoding
+fn build_reader(input: &str, delimiter: char, header: bool) -> Result<Reader<Box<dyn Read>>> {
+    let reader = ReaderBuilder::new()
+        .delimiter(delimiter as u8)
+        .has_headers(header)
+        .from_reader(encoding::decode_reader(get_reader(input)?, None)?);
+    Ok(reader)
+}
+
+/// read the header row, or synthesize `column_1..column_n` for headerless input
+fn read_headers<R: Read>(reader: &mut Reader<R>, header: bool) -> Result<Vec<String>> {
+    let first = reader.headers()?;
+    let headers = if header {
+        first.iter().map(|h| h.to_string()).collect()
+    } else {
+        (1..=first.len()).map(|i| format!("column_{}", i)).collect()
+    };
+    Ok(headers)
+}
+
+/// stream a CSV with a header row as untyped string records
+fn read_text_records(input: &str, delimiter: char) -> Result<(Vec<String>, Records)> {
+    let mut reader = build_reader(input, delimiter, true)?;
+    let headers = read_headers(&mut reader, true)?;
+    let typings = vec![ColumnTyping::Text; headers.len()];
+    let columns = headers.clone();
+    let records = reader
+        .into_records()
+        .map(move |record| to_json_record(&columns, &typings, &record?));
+    Ok((headers, Box::new(records)))
+}
+
+/// combine inferred types with user overrides, declared types always win
+fn resolve_typings(
+    headers: &[String],
+    inferred: Option<&[ColumnType]>,
+    overrides: &[(String, ColumnType)],
+) -> Result<Vec<ColumnTyping>> {
+    let mut typings = match inferred {
+        Some(types) => types.iter().map(|ty| ColumnTyping::Inferred(*ty)).collect(),
+        None => vec![ColumnTyping::Text; headers.len()],
+    };
+    for (name, ty) in overrides {
+        let index = headers
+            .iter()
+            .position(|h| h == name)
+            .ok_or_else(|| anyhow::format_err!("Unknown column in --types: {}", name))?;
+        typings[index] = ColumnTyping::Declared(*ty);
+    }
+    Ok(typings)
+}
+
+fn to_json_record(
+    headers: &[String],
+    typings: &[ColumnTyping],
+    record: &StringRecord,
+) -> Result<Map<String, Value>> {
+    let mut ret = Map::with_capacity(headers.len());
+    for ((name, typing), value) in headers.iter().zip(typings).zip(record.iter()) {
+        let value = typing.convert(value).map_err(|e| {
+            let line = record.position().map(|p| p.line()).unwrap_or_default();
+            anyhow::format_err!("Line {}, column {}: {}", line, name, e)
+        })?;
+        ret.insert(name.clone(), value);
+    }
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::{Cli, Commands};
+    use clap::Parser;
+
+    #[test]
+    fn test_read_headers_synthesized() -> Result<()> {
+        let data = "a;1\nb;2\n";
+        let mut reader = ReaderBuilder::new()
+            .delimiter(b';')
+            .has_headers(false)
+            .from_reader(data.as_bytes());
+        let headers = read_headers(&mut reader, false)?;
+        assert_eq!(headers, vec!["column_1", "column_2"]);
+
+        let records = reader.records().collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(records.len(), 2);
+        let typings = vec![ColumnTyping::Text; 2];
+        let record = to_json_record(&headers, &typings, &records[0])?;
+        assert_eq!(record["column_1"], "a");
+        assert_eq!(record["column_2"], "1");
+        Ok(())
+    }
+
+    #[test]
+    fn test_documents_to_csv_union_columns() -> Result<()> {
+        let dir = std::env::temp_dir();
+        let input = dir.join(format!("rcli-union-{}.json", std::process::id()));
+        let output = dir.join(format!("rcli-union-{}.csv", std::process::id()));
+        std::fs::write(
+            &input,
+            r#"[{}, {"name": "a", "tags": ["x"]}, {"name": "b", "tags": ["x", "y"], "address": {"city": "Turin"}}]"#,
+        )?;
+        let run = |arrays: &str| -> Result<String> {
+            let cli = Cli::try_parse_from([
+                "rcli",
+                "csv",
+                "-i",
+                &input.to_string_lossy(),
+                "-o",
+                &output.to_string_lossy(),
+                "-f",
+                "csv",
+                "--arrays",
+                arrays,
+            ])?;
+            let Commands::Csv(options) = cli.command else {
+                unreachable!("parsed a csv command");
+            };
+            process_csv(&options)?;
+            Ok(std::fs::read_to_string(&output)?)
+        };
+        assert_eq!(
+            run("index")?,
+            "name,tags[0],tags[1],address.city\n,,,\na,x,,\nb,x,y,Turin\n"
+        );
+        assert_eq!(
+            run("join")?,
+            "name,tags,address.city\n,,\na,x,\nb,x;y,Turin\n"
+        );
+        std::fs::remove_file(&input)?;
+        std::fs::remove_file(&output)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_typings_with_overrides() -> Result<()> {
+        let headers = vec!["age".to_string(), "active".to_string()];
+        let inferred = [ColumnType::Integer, ColumnType::String];
+        let overrides = vec![("active".to_string(), ColumnType::Boolean)];
+        let typings = resolve_typings(&headers, Some(&inferred), &overrides)?;
+        assert_eq!(
+            typings,
+            vec![
+                ColumnTyping::Inferred(ColumnType::Integer),
+                ColumnTyping::Declared(ColumnType::Boolean),
+            ]
+        );
+
+        let record = StringRecord::from(vec!["30", "yes"]);
+        let record = to_json_record(&headers, &typings, &record)?;
+        assert_eq!(record["age"], 30);
+        assert_eq!(record["active"], true);
+
+        let unknown = vec![("missing".to_string(), ColumnType::Integer)];
+        assert!(resolve_typings(&headers, None, &unknown).is_err());
+        Ok(())
+    }
 }
@@ -0,0 +1,90 @@
+use anyhow::Result;
+use indexmap::IndexSet;
+use serde_json::Value;
+
+use super::{read_text_records, source::Record, writer};
+use crate::{cli::csv_options::CsvCatOptions, utils::get_writer};
+
+/// stream every file in turn, the output columns are the union of all headers in first-seen order
+///
+/// every record is reconciled to those columns, so csv output can write its header up front
+pub fn process_csv_cat(options: &CsvCatOptions) -> Result<()> {
+    let mut inputs = Vec::with_capacity(options.inputs.len());
+    let mut columns = IndexSet::new();
+    if let Some(source) = &options.source_column {
+        columns.insert(source.clone());
+    }
+    for input in &options.inputs {
+        let (headers, records) = read_text_records(input, options.delimiter)?;
+        if let Some(source) = options
+            .source_column
+            .as_ref()
+            .filter(|s| headers.contains(s))
+        {
+            return Err(anyhow::format_err!(
+                "Source column {} is already a column of {}",
+                source,
+                input
+            ));
+        }
+        columns.extend(headers);
+        inputs.push((input, records));
+    }
+
+    let mut writer = writer::new_writer(
+        options.format,
+        get_writer(&options.output)?,
+        Default::default(),
+    );
+    for (input, records) in inputs {
+        for record in records {
+            let mut record = record?;
+            if let Some(source) = &options.source_column {
+                record.insert(source.clone(), input.as_str().into());
+            }
+            writer.write_record(&reconcile(record, &columns))?;
+        }
+    }
+    writer.finish()
+}
+
+/// reorder the record to the given columns, filling missing ones with null
+fn reconcile(mut record: Record, columns: &IndexSet<String>) -> Record {
+    columns
+        .iter()
+        .map(|column| {
+            let value = record.remove(column).unwrap_or(Value::Null);
+            (column.clone(), value)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::csv_options::OutputFormat;
+    use serde_json::json;
+
+    #[test]
+    fn test_reconcile() {
+        let columns = ["a", "b", "c"].map(String::from).into_iter().collect();
+        let record = json!({"c": "3", "a": "1"}).as_object().unwrap().clone();
+        let record = reconcile(record, &columns);
+        assert_eq!(
+            serde_json::to_string(&record).unwrap(),
+            r#"{"a":"1","b":null,"c":"3"}"#
+        );
+    }
+
+    #[test]
+    fn test_source_column_collision() {
+        let options = CsvCatOptions {
+            inputs: vec!["assets/juventus.csv".into()],
+            source_column: Some("Name".into()),
+            delimiter: ',',
+            output: "-".into(),
+            format: OutputFormat::Csv,
+        };
+        assert!(process_csv_cat(&options).is_err());
+    }
+}
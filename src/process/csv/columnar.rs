@@ -0,0 +1,355 @@
+use anyhow::Result;
+use arrow_array::{
+    builder::{BooleanBuilder, Float64Builder, Int64Builder, StringBuilder},
+    ArrayRef, RecordBatch,
+};
+use arrow_ipc::writer::FileWriter;
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use parquet::{
+    arrow::ArrowWriter,
+    basic::{Compression as ParquetCompression, GzipLevel, ZstdLevel},
+    file::properties::WriterProperties,
+};
+use serde_json::{Map, Value};
+use std::{io::Write, sync::Arc};
+
+use super::{
+    flatten::{flatten_values, to_cell},
+    source::Record,
+    writer::{RecordWriter, WriteOptions},
+};
+use crate::cli::csv_options::Compression;
+
+/// rows per record batch, the schema is inferred from the first one
+const BATCH_SIZE: usize = 8192;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColumnarFormat {
+    Parquet,
+    Arrow,
+}
+
+enum Sink {
+    /// parquet needs a `Send` writer, so it writes to a buffer that is drained after every batch
+    Parquet(ArrowWriter<Vec<u8>>),
+    Arrow(FileWriter<Box<dyn Write>>),
+}
+
+/// Parquet or Arrow IPC file, written in batches of flattened records
+///
+/// columns are typed from the JSON values of the first batch, so use `--infer` or `--types`
+/// to get anything but strings out of CSV input
+pub struct ColumnarWriter {
+    format: ColumnarFormat,
+    output: Option<Box<dyn Write>>,
+    options: WriteOptions,
+    schema: Option<SchemaRef>,
+    sink: Option<Sink>,
+    rows: Vec<Record>,
+}
+
+impl ColumnarWriter {
+    pub fn new(format: ColumnarFormat, output: Box<dyn Write>, options: WriteOptions) -> Self {
+        Self {
+            format,
+            output: Some(output),
+            options,
+            schema: None,
+            sink: None,
+            rows: Vec::with_capacity(BATCH_SIZE),
+        }
+    }
+
+    fn write_batch(&mut self) -> Result<()> {
+        let schema = match &self.schema {
+            Some(schema) => schema.clone(),
+            None => {
+                let schema = infer_schema(&self.rows);
+                self.sink = Some(self.open(&schema)?);
+                self.schema = Some(schema.clone());
+                schema
+            }
+        };
+        for row in &self.rows {
+            if let Some(column) = row.keys().find(|k| schema.field_with_name(k).is_err()) {
+                return Err(anyhow::format_err!(
+                    "Column {} does not appear in the first {} records, the schema is already written",
+                    column,
+                    BATCH_SIZE
+                ));
+            }
+        }
+        let columns = schema
+            .fields()
+            .iter()
+            .map(|field| build_array(field, &self.rows))
+            .collect::<Result<Vec<_>>>()?;
+        let batch = RecordBatch::try_new(schema, columns)?;
+        self.rows.clear();
+
+        match self.sink.as_mut() {
+            Some(Sink::Parquet(writer)) => {
+                writer.write(&batch)?;
+                drain(writer, self.output.as_mut())?;
+            }
+            Some(Sink::Arrow(writer)) => writer.write(&batch)?,
+            None => {}
+        }
+        Ok(())
+    }
+
+    fn open(&mut self, schema: &SchemaRef) -> Result<Sink> {
+        let sink = match self.format {
+            ColumnarFormat::Parquet => {
+                let properties = WriterProperties::builder()
+                    .set_compression(parquet_compression(self.options.compression))
+                    .build();
+                Sink::Parquet(ArrowWriter::try_new(
+                    Vec::new(),
+                    schema.clone(),
+                    Some(properties),
+                )?)
+            }
+            ColumnarFormat::Arrow => {
+                let output = self
+                    .output
+                    .take()
+                    .ok_or_else(|| anyhow::format_err!("Output already taken"))?;
+                Sink::Arrow(FileWriter::try_new(output, schema)?)
+            }
+        };
+        Ok(sink)
+    }
+}
+
+impl RecordWriter for ColumnarWriter {
+    fn write_record(&mut self, record: &Map<String, Value>) -> Result<()> {
+        let row = flatten_values(record, self.options.arrays);
+        self.rows.push(row.into_iter().collect());
+        if self.rows.len() == BATCH_SIZE {
+            self.write_batch()?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        if self.rows.is_empty() && self.schema.is_none() {
+            return Err(anyhow::format_err!(
+                "No records to write, {:?} output takes its columns from the records",
+                self.format
+            ));
+        }
+        if !self.rows.is_empty() {
+            self.write_batch()?;
+        }
+        match self.sink.take() {
+            Some(Sink::Parquet(mut writer)) => {
+                writer.finish()?;
+                drain(&mut writer, self.output.as_mut())?;
+            }
+            Some(Sink::Arrow(mut writer)) => {
+                writer.finish()?;
+                writer.get_mut().flush()?;
+            }
+            None => {}
+        }
+        if let Some(output) = self.output.as_mut() {
+            output.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// move whatever parquet has written so far to the real output
+fn drain(writer: &mut ArrowWriter<Vec<u8>>, output: Option<&mut Box<dyn Write>>) -> Result<()> {
+    let bytes = std::mem::take(writer.inner_mut());
+    if let Some(output) = output {
+        output.write_all(&bytes)?;
+    }
+    Ok(())
+}
+
+fn parquet_compression(compression: Compression) -> ParquetCompression {
+    match compression {
+        Compression::None => ParquetCompression::UNCOMPRESSED,
+        Compression::Snappy => ParquetCompression::SNAPPY,
+        Compression::Gzip => ParquetCompression::GZIP(GzipLevel::default()),
+        Compression::Zstd => ParquetCompression::ZSTD(ZstdLevel::default()),
+        Compression::Lz4 => ParquetCompression::LZ4_RAW,
+    }
+}
+
+/// columns in first-seen order, all nullable
+fn infer_schema(rows: &[Record]) -> SchemaRef {
+    let mut fields: Vec<(String, Option<DataType>)> = Vec::new();
+    for row in rows {
+        for (key, value) in row {
+            let index = match fields.iter().position(|(name, _)| name == key) {
+                Some(index) => index,
+                None => {
+                    fields.push((key.clone(), None));
+                    fields.len() - 1
+                }
+            };
+            let ty = match value {
+                Value::Null => continue,
+                Value::Bool(_) => DataType::Boolean,
+                Value::Number(n) if n.is_i64() => DataType::Int64,
+                Value::Number(_) => DataType::Float64,
+                _ => DataType::Utf8,
+            };
+            fields[index].1 = Some(match fields[index].1.take() {
+                None => ty,
+                Some(current) if current == ty => ty,
+                Some(DataType::Int64 | DataType::Float64)
+                    if matches!(ty, DataType::Int64 | DataType::Float64) =>
+                {
+                    DataType::Float64
+                }
+                Some(_) => DataType::Utf8,
+            });
+        }
+    }
+    let fields = fields
+        .into_iter()
+        .map(|(name, ty)| Field::new(name, ty.unwrap_or(DataType::Utf8), true))
+        .collect::<Vec<_>>();
+    Arc::new(Schema::new(fields))
+}
+
+fn build_array(field: &Field, rows: &[Record]) -> Result<ArrayRef> {
+    let name = field.name();
+    let values = rows.iter().map(|row| row.get(name).unwrap_or(&Value::Null));
+    let mismatch = |value: &Value| {
+        anyhow::format_err!(
+            "Column {}: {} does not fit the {} type of earlier records, declare it with --types",
+            name,
+            value,
+            field.data_type()
+        )
+    };
+    let array: ArrayRef = match field.data_type() {
+        DataType::Boolean => {
+            let mut builder = BooleanBuilder::with_capacity(rows.len());
+            for value in values {
+                match value {
+                    Value::Null => builder.append_null(),
+                    Value::Bool(b) => builder.append_value(*b),
+                    value => return Err(mismatch(value)),
+                }
+            }
+            Arc::new(builder.finish())
+        }
+        DataType::Int64 => {
+            let mut builder = Int64Builder::with_capacity(rows.len());
+            for value in values {
+                match value {
+                    Value::Null => builder.append_null(),
+                    value => builder.append_value(value.as_i64().ok_or_else(|| mismatch(value))?),
+                }
+            }
+            Arc::new(builder.finish())
+        }
+        DataType::Float64 => {
+            let mut builder = Float64Builder::with_capacity(rows.len());
+            for value in values {
+                match value {
+                    Value::Null => builder.append_null(),
+                    value => builder.append_value(value.as_f64().ok_or_else(|| mismatch(value))?),
+                }
+            }
+            Arc::new(builder.finish())
+        }
+        _ => {
+            let mut builder = StringBuilder::with_capacity(rows.len(), rows.len() * 8);
+            for value in values {
+                match value {
+                    Value::Null => builder.append_null(),
+                    value => builder.append_value(to_cell(value)),
+                }
+            }
+            Arc::new(builder.finish())
+        }
+    };
+    Ok(array)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::testing::Sink;
+    use arrow_array::{Array, Float64Array, Int64Array, StringArray};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use serde_json::json;
+
+    fn write(format: ColumnarFormat, records: &[Value]) -> Result<Vec<u8>> {
+        let sink = Sink::default();
+        let mut writer = Box::new(ColumnarWriter::new(
+            format,
+            Box::new(sink.clone()),
+            Default::default(),
+        ));
+        for record in records {
+            writer.write_record(record.as_object().unwrap())?;
+        }
+        writer.finish()?;
+        let bytes = sink.0.borrow().clone();
+        Ok(bytes)
+    }
+
+    #[test]
+    fn test_parquet_round_trip() -> Result<()> {
+        let records = vec![
+            json!({"name": "Buffon", "age": 46, "rating": 8, "club": {"city": "Turin"}}),
+            json!({"name": "Chiellini", "age": null, "rating": 7.5, "club": {"city": null}}),
+        ];
+        let path = std::env::temp_dir().join(format!("rcli-{}.parquet", std::process::id()));
+        std::fs::write(&path, write(ColumnarFormat::Parquet, &records)?)?;
+        let file = std::fs::File::open(&path)?;
+        let reader = ParquetRecordBatchReaderBuilder::try_new(file)?.build()?;
+        let batches = reader.collect::<Result<Vec<_>, _>>()?;
+        let batch = &batches[0];
+        assert_eq!(batch.num_rows(), 2);
+
+        let schema = batch.schema();
+        let names = schema.fields().iter().map(|f| f.name().as_str());
+        assert_eq!(
+            names.collect::<Vec<_>>(),
+            vec!["name", "age", "rating", "club.city"]
+        );
+        let age = batch
+            .column(1)
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        assert_eq!(age.value(0), 46);
+        assert!(age.is_null(1));
+        let rating = batch.column(2).as_any().downcast_ref::<Float64Array>();
+        assert_eq!(rating.unwrap().value(1), 7.5);
+        let city = batch.column(3).as_any().downcast_ref::<StringArray>();
+        assert_eq!(city.unwrap().value(0), "Turin");
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_arrow_ipc() -> Result<()> {
+        let records = vec![json!({"a": 1}), json!({"a": 2})];
+        let bytes = write(ColumnarFormat::Arrow, &records)?;
+        let reader = arrow_ipc::reader::FileReader::try_new(std::io::Cursor::new(bytes), None)?;
+        let rows = reader
+            .map(|batch| batch.map(|b| b.num_rows()))
+            .sum::<Result<usize, _>>()?;
+        assert_eq!(rows, 2);
+
+        let mixed = [json!({"a": true}), json!({"a": 1})];
+        let schema = infer_schema(&[]);
+        assert!(schema.fields().is_empty());
+        let rows = mixed
+            .iter()
+            .map(|v| v.as_object().unwrap().clone())
+            .collect::<Vec<_>>();
+        assert_eq!(infer_schema(&rows).field(0).data_type(), &DataType::Utf8);
+        Ok(())
+    }
+}
@@ -0,0 +1,104 @@
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+
+use super::{expr, read_text_records, source::Record, writer};
+use crate::{
+    cli::csv_options::{CsvDedupOptions, Keep},
+    utils::get_writer,
+};
+
+pub fn process_csv_dedup(options: &CsvDedupOptions) -> Result<()> {
+    let (headers, records) = read_text_records(&options.input, options.delimiter)?;
+    for key in &options.key {
+        if !headers.contains(key) {
+            return Err(anyhow::format_err!("Unknown key column: {}", key));
+        }
+    }
+
+    let mut writer = writer::new_writer(
+        options.format,
+        get_writer(&options.output)?,
+        Default::default(),
+    );
+    dedup(records, &options.key, options.keep, |record| {
+        writer.write_record(&record)
+    })?;
+    writer.finish()
+}
+
+/// keeping the first occurrence streams, keeping the last buffers one record per key
+fn dedup(
+    records: impl Iterator<Item = Result<Record>>,
+    keys: &[String],
+    keep: Keep,
+    mut emit: impl FnMut(Record) -> Result<()>,
+) -> Result<()> {
+    let key_of = |record: &Record| -> Vec<String> {
+        if keys.is_empty() {
+            record.values().map(expr::as_text).collect()
+        } else {
+            keys.iter()
+                .map(|k| record.get(k).map(expr::as_text).unwrap_or_default())
+                .collect()
+        }
+    };
+
+    match keep {
+        Keep::First => {
+            let mut seen = HashSet::new();
+            for record in records {
+                let record = record?;
+                if seen.insert(key_of(&record)) {
+                    emit(record)?;
+                }
+            }
+        }
+        Keep::Last => {
+            // the position of the last occurrence keeps the output in that order
+            let mut last = HashMap::new();
+            for (i, record) in records.enumerate() {
+                let record = record?;
+                last.insert(key_of(&record), (i, record));
+            }
+            let mut kept = last.into_values().collect::<Vec<_>>();
+            kept.sort_unstable_by_key(|(i, _)| *i);
+            for (_, record) in kept {
+                emit(record)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn run(keys: &[&str], keep: Keep) -> Result<Vec<Value>> {
+        let input = vec![
+            json!({"id": "1", "v": "a"}),
+            json!({"id": "2", "v": "b"}),
+            json!({"id": "1", "v": "c"}),
+            json!({"id": "2", "v": "b"}),
+        ];
+        let records = input
+            .into_iter()
+            .map(|v| Ok(v.as_object().unwrap().clone()));
+        let keys = keys.iter().map(|k| k.to_string()).collect::<Vec<_>>();
+        let mut output = Vec::new();
+        dedup(records, &keys, keep, |r| {
+            output.push(r["v"].clone());
+            Ok(())
+        })?;
+        Ok(output)
+    }
+
+    #[test]
+    fn test_dedup() -> Result<()> {
+        assert_eq!(run(&[], Keep::First)?, vec!["a", "b", "c"]);
+        assert_eq!(run(&["id"], Keep::First)?, vec!["a", "b"]);
+        assert_eq!(run(&["id"], Keep::Last)?, vec!["c", "b"]);
+        Ok(())
+    }
+}
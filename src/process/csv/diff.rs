@@ -0,0 +1,215 @@
+use anyhow::Result;
+use indexmap::{IndexMap, IndexSet};
+use serde_json::Value;
+
+use super::{expr, read_text_records, source::Record, writer};
+use crate::{cli::csv_options::CsvDiffOptions, utils::get_writer};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Change {
+    Added(Record),
+    Removed(Record),
+    /// the new row and the `(column, before, after)` cells that differ
+    Changed(Record, Vec<(String, Value, Value)>),
+}
+
+impl Change {
+    /// the change name and the row it describes
+    fn parts(&self) -> (&'static str, &Record) {
+        match self {
+            Change::Added(row) => ("added", row),
+            Change::Removed(row) => ("removed", row),
+            Change::Changed(row, _) => ("changed", row),
+        }
+    }
+}
+
+pub fn process_csv_diff(options: &CsvDiffOptions) -> Result<()> {
+    let (old_headers, old) = read_rows(&options.old, options.delimiter)?;
+    let (new_headers, new) = read_rows(&options.new, options.delimiter)?;
+    for key in &options.key {
+        if !old_headers.contains(key) || !new_headers.contains(key) {
+            return Err(anyhow::format_err!(
+                "Key column {} is not in both files",
+                key
+            ));
+        }
+    }
+    let columns = new_headers
+        .into_iter()
+        .chain(old_headers)
+        .collect::<IndexSet<_>>();
+    let changes = diff(old, new, &options.key)?;
+
+    let mut writer = writer::new_writer(
+        options.format,
+        get_writer(&options.output)?,
+        Default::default(),
+    );
+    for change in &changes {
+        if options.patch {
+            writer.write_record(&patch_record(change, &columns))?;
+        } else {
+            for record in cell_records(change, &options.key) {
+                writer.write_record(&record)?;
+            }
+        }
+    }
+    writer.finish()
+}
+
+fn read_rows(path: &str, delimiter: char) -> Result<(Vec<String>, Vec<Record>)> {
+    let (headers, records) = read_text_records(path, delimiter)?;
+    Ok((headers, records.collect::<Result<_>>()?))
+}
+
+fn key_of(record: &Record, keys: &[String]) -> Vec<String> {
+    keys.iter()
+        .map(|k| record.get(k).map(expr::as_text).unwrap_or_default())
+        .collect()
+}
+
+/// changes in the order of the new file, followed by the removed rows in the order of the old file
+fn diff(old: Vec<Record>, new: Vec<Record>, keys: &[String]) -> Result<Vec<Change>> {
+    let mut old_rows = IndexMap::with_capacity(old.len());
+    for record in old {
+        let key = key_of(&record, keys);
+        if old_rows.insert(key.clone(), record).is_some() {
+            return Err(anyhow::format_err!("Duplicate key in old file: {:?}", key));
+        }
+    }
+
+    let mut changes = Vec::new();
+    let mut seen = IndexSet::with_capacity(new.len());
+    for record in new {
+        let key = key_of(&record, keys);
+        if !seen.insert(key.clone()) {
+            return Err(anyhow::format_err!("Duplicate key in new file: {:?}", key));
+        }
+        match old_rows.shift_remove(&key) {
+            Some(before) => {
+                let columns = record.keys().chain(before.keys()).collect::<IndexSet<_>>();
+                let cells = columns
+                    .into_iter()
+                    .filter_map(|column| {
+                        let old_value = before.get(column).cloned().unwrap_or(Value::Null);
+                        let new_value = record.get(column).cloned().unwrap_or(Value::Null);
+                        (old_value != new_value).then(|| (column.clone(), old_value, new_value))
+                    })
+                    .collect::<Vec<_>>();
+                if !cells.is_empty() {
+                    changes.push(Change::Changed(record, cells));
+                }
+            }
+            None => changes.push(Change::Added(record)),
+        }
+    }
+    changes.extend(old_rows.into_values().map(Change::Removed));
+    Ok(changes)
+}
+
+/// one record per changed cell, added and removed rows get a single record each
+fn cell_records(change: &Change, keys: &[String]) -> Vec<Record> {
+    let (name, row) = change.parts();
+    let base = || {
+        let mut record = Record::new();
+        record.insert("change".into(), name.into());
+        for key in keys {
+            record.insert(key.clone(), row.get(key).cloned().unwrap_or(Value::Null));
+        }
+        record
+    };
+    match change {
+        Change::Changed(_, cells) => cells
+            .iter()
+            .map(|(column, before, after)| {
+                let mut record = base();
+                record.insert("column".into(), column.clone().into());
+                record.insert("before".into(), before.clone());
+                record.insert("after".into(), after.clone());
+                record
+            })
+            .collect(),
+        _ => {
+            let mut record = base();
+            for field in ["column", "before", "after"] {
+                record.insert(field.into(), Value::Null);
+            }
+            vec![record]
+        }
+    }
+}
+
+/// the full row with an `_op` column, removed rows keep their old values
+fn patch_record(change: &Change, columns: &IndexSet<String>) -> Record {
+    let (op, row) = change.parts();
+    let mut record = Record::with_capacity(columns.len() + 1);
+    record.insert("_op".into(), op.into());
+    for column in columns {
+        let value = row.get(column).cloned().unwrap_or(Value::Null);
+        record.insert(column.clone(), value);
+    }
+    record
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn rows(values: Vec<Value>) -> Vec<Record> {
+        values
+            .into_iter()
+            .map(|v| v.as_object().unwrap().clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_diff() -> Result<()> {
+        let old = rows(vec![
+            json!({"Name": "a", "Kit": "1", "Pos": "GK"}),
+            json!({"Name": "b", "Kit": "2", "Pos": "DF"}),
+            json!({"Name": "c", "Kit": "3", "Pos": "MF"}),
+        ]);
+        let new = rows(vec![
+            json!({"Name": "d", "Kit": "4", "Pos": "FW"}),
+            json!({"Name": "c", "Kit": "3", "Pos": "MF"}),
+            json!({"Name": "a", "Kit": "10", "Pos": "GK"}),
+        ]);
+        let keys = vec!["Name".to_string()];
+        let changes = diff(old, new.clone(), &keys)?;
+        assert_eq!(changes.len(), 3);
+        assert_eq!(changes[0], Change::Added(new[0].clone()));
+        assert_eq!(
+            changes[1],
+            Change::Changed(
+                new[2].clone(),
+                vec![("Kit".into(), json!("1"), json!("10"))]
+            )
+        );
+        assert!(matches!(&changes[2], Change::Removed(row) if row["Name"] == "b"));
+
+        let records = cell_records(&changes[1], &keys);
+        assert_eq!(
+            Value::Object(records[0].clone()),
+            json!({"change": "changed", "Name": "a", "column": "Kit", "before": "1", "after": "10"})
+        );
+
+        let columns = ["Name", "Kit", "Pos"]
+            .map(String::from)
+            .into_iter()
+            .collect();
+        let patch = patch_record(&changes[2], &columns);
+        assert_eq!(
+            Value::Object(patch),
+            json!({"_op": "removed", "Name": "b", "Kit": "2", "Pos": "DF"})
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_duplicate_key() {
+        let old = rows(vec![json!({"Name": "a"}), json!({"Name": "a"})]);
+        assert!(diff(old, vec![], &["Name".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,241 @@
+use anyhow::Result;
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoder, EncoderResult, Encoding, UTF_16BE, UTF_16LE, UTF_8};
+use encoding_rs_io::DecodeReaderBytesBuilder;
+use std::io::{self, Cursor, Read, Write};
+
+/// bytes sniffed from the start of the input when detecting its encoding
+const SAMPLE_SIZE: usize = 64 * 1024;
+
+/// transcode the input to UTF-8, the encoding is detected when not given,
+/// a leading BOM is always stripped and wins over the given encoding
+pub fn decode_reader(
+    mut reader: Box<dyn Read>,
+    encoding: Option<&'static Encoding>,
+) -> Result<Box<dyn Read>> {
+    let mut sample = Vec::with_capacity(SAMPLE_SIZE);
+    (&mut reader)
+        .take(SAMPLE_SIZE as u64)
+        .read_to_end(&mut sample)?;
+    let eof = sample.len() < SAMPLE_SIZE;
+    let encoding = encoding.unwrap_or_else(|| detect(&sample, eof));
+
+    let reader = Cursor::new(sample).chain(reader);
+    let reader = DecodeReaderBytesBuilder::new()
+        .encoding(Some(encoding))
+        .bom_override(true)
+        .build(reader);
+    Ok(Box::new(reader))
+}
+
+/// BOM first, then valid UTF-8, then NUL patterns for BOM-less UTF-16,
+/// anything else is left to chardetng
+fn detect(sample: &[u8], eof: bool) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(sample) {
+        return encoding;
+    }
+    match std::str::from_utf8(sample) {
+        Ok(_) => return UTF_8,
+        // the sample may end in the middle of a character, unless it is the whole input
+        Err(e) if !eof && e.error_len().is_none() => return UTF_8,
+        Err(_) => {}
+    }
+
+    let (mut even, mut odd) = (0, 0);
+    for (i, b) in sample.iter().enumerate() {
+        if *b == 0 {
+            if i % 2 == 0 {
+                even += 1;
+            } else {
+                odd += 1;
+            }
+        }
+    }
+    let half = sample.len() / 2;
+    if odd > half / 2 && even == 0 {
+        return UTF_16LE;
+    }
+    if even > half / 2 && odd == 0 {
+        return UTF_16BE;
+    }
+
+    let mut detector = EncodingDetector::new();
+    detector.feed(sample, eof);
+    detector.guess(None, true)
+}
+
+/// transcode UTF-8 output to the given encoding
+pub fn encode_writer(writer: Box<dyn Write>, encoding: &'static Encoding) -> Box<dyn Write> {
+    if encoding == UTF_8 {
+        return writer;
+    }
+    Box::new(EncodeWriter {
+        inner: writer,
+        encoding,
+        encoder: encoding.new_encoder(),
+        pending: Vec::new(),
+        started: false,
+    })
+}
+
+struct EncodeWriter {
+    inner: Box<dyn Write>,
+    encoding: &'static Encoding,
+    encoder: Encoder,
+    /// trailing bytes of a character split across writes
+    pending: Vec<u8>,
+    started: bool,
+}
+
+impl EncodeWriter {
+    /// `last` returns a stateful encoding such as ISO-2022-JP to its initial state
+    fn encode(&mut self, text: &str, last: bool) -> io::Result<Vec<u8>> {
+        // encoding_rs only decodes UTF-16, encoding it is done here with a BOM up front
+        if self.encoding == UTF_16LE || self.encoding == UTF_16BE {
+            let le = self.encoding == UTF_16LE;
+            let bom = (!self.started).then_some('\u{feff}');
+            let bytes = bom
+                .into_iter()
+                .chain(text.chars())
+                .flat_map(|c| {
+                    let mut units = [0; 2];
+                    c.encode_utf16(&mut units).to_vec()
+                })
+                .flat_map(|unit| {
+                    if le {
+                        unit.to_le_bytes()
+                    } else {
+                        unit.to_be_bytes()
+                    }
+                })
+                .collect();
+            return Ok(bytes);
+        }
+
+        let capacity = self
+            .encoder
+            .max_buffer_length_from_utf8_without_replacement(text.len())
+            .unwrap_or(text.len() * 4 + 8);
+        let mut output = vec![0; capacity];
+        let (result, _, written) =
+            self.encoder
+                .encode_from_utf8_without_replacement(text, &mut output, last);
+        if let EncoderResult::Unmappable(c) = result {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Character {:?} cannot be encoded as {}",
+                    c,
+                    self.encoding.name()
+                ),
+            ));
+        }
+        output.truncate(written);
+        Ok(output)
+    }
+}
+
+impl Write for EncodeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        let valid = match std::str::from_utf8(&self.pending) {
+            Ok(text) => text.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        };
+        let pending = std::mem::take(&mut self.pending);
+        let text = std::str::from_utf8(&pending[..valid])
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let bytes = self.encode(text, false)?;
+        self.started = true;
+        self.inner.write_all(&bytes)?;
+        self.pending = pending[valid..].to_vec();
+        Ok(buf.len())
+    }
+
+    /// finalizes the encoder, a fresh one carries on if more is written
+    fn flush(&mut self) -> io::Result<()> {
+        if self.started {
+            let bytes = self.encode("", true)?;
+            self.inner.write_all(&bytes)?;
+            self.encoder = self.encoding.new_encoder();
+        }
+        self.inner.flush()
+    }
+}
+
+impl Drop for EncodeWriter {
+    fn drop(&mut self) {
+        // errors can't be reported here, writers flush in `finish` where they are
+        let _ = self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::testing::Sink;
+    use encoding_rs::{GBK, ISO_2022_JP, WINDOWS_1252};
+
+    fn decode(bytes: Vec<u8>, encoding: Option<&'static Encoding>) -> Result<String> {
+        let mut text = String::new();
+        decode_reader(Box::new(Cursor::new(bytes)), encoding)?.read_to_string(&mut text)?;
+        Ok(text)
+    }
+
+    #[test]
+    fn test_decode_reader() -> Result<()> {
+        let text = "姓名,球队,城市\n布冯,尤文图斯,都灵\n基耶利尼,尤文图斯,都灵\n";
+        let (gbk, _, _) = GBK.encode(text);
+        assert_eq!(decode(gbk.to_vec(), None)?, text);
+
+        let text = "Name,City\nJosé,Málaga\nFrançois,Besançon\n";
+        let (latin, _, _) = WINDOWS_1252.encode(text);
+        assert_eq!(decode(latin.to_vec(), None)?, text);
+        assert_eq!(decode(latin.to_vec(), Some(WINDOWS_1252))?, text);
+
+        let utf16 = [0xff, 0xfe]
+            .into_iter()
+            .chain(text.encode_utf16().flat_map(|u| u.to_le_bytes()))
+            .collect();
+        assert_eq!(decode(utf16, None)?, text);
+        let utf16 = text.encode_utf16().flat_map(|u| u.to_be_bytes()).collect();
+        assert_eq!(decode(utf16, None)?, text);
+
+        let bom = [0xef, 0xbb, 0xbf].into_iter().chain(text.bytes()).collect();
+        assert_eq!(decode(bom, None)?, text);
+
+        // a Latin-1 byte at the very end is not a truncated UTF-8 character
+        assert_eq!(decode(b"Name\nCaf\xe9".to_vec(), None)?, "Name\nCaf\u{e9}");
+        assert_eq!(detect(b"Name\nCaf\xc3", false), UTF_8);
+        Ok(())
+    }
+
+    #[test]
+    fn test_encode_writer() -> Result<()> {
+        let text = "Name,City\nJosé,Málaga\n".as_bytes();
+        for encoding in [WINDOWS_1252, UTF_16LE, UTF_16BE] {
+            let sink = Sink::default();
+            let mut writer = encode_writer(Box::new(sink.clone()), encoding);
+            // split in the middle of `é`
+            writer.write_all(&text[..14])?;
+            writer.write_all(&text[14..])?;
+            writer.flush()?;
+            let bytes = sink.0.borrow().clone();
+            assert_eq!(decode(bytes, Some(encoding))?, "Name,City\nJosé,Málaga\n");
+        }
+
+        let mut writer = encode_writer(Box::new(Sink::default()), WINDOWS_1252);
+        assert!(writer.write_all("尤文图斯".as_bytes()).is_err());
+
+        // ISO-2022-JP has to switch back to ASCII at the end
+        let sink = Sink::default();
+        let mut writer = encode_writer(Box::new(sink.clone()), ISO_2022_JP);
+        writer.write_all("名前\n日本".as_bytes())?;
+        writer.flush()?;
+        let bytes = sink.0.borrow().clone();
+        assert!(bytes.ends_with(b"\x1b(B"));
+        assert_eq!(decode(bytes, Some(ISO_2022_JP))?, "名前\n日本");
+        Ok(())
+    }
+}
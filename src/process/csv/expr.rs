@@ -0,0 +1,643 @@
+use anyhow::Result;
+use serde_json::{Number, Value};
+use std::{cmp::Ordering, fmt, str::FromStr};
+
+use super::{functions, source::Record};
+
+/// a small expression language over record fields, e.g. `Position == "Goalkeeper" && Age > 30`
+///
+/// bare identifiers refer to columns, names with spaces are quoted with backticks (`` `Kit Number` ``),
+/// an identifier followed by parentheses calls a built-in function like `upper(Name)`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Literal(Value),
+    Column(String),
+    Call(String, Vec<Expr>),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Not,
+    Neg,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+impl FromStr for Expr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(s)?;
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            depth: 0,
+        };
+        let expr = parser.parse_or()?;
+        match parser.peek() {
+            Token::End => Ok(expr),
+            token => Err(anyhow::format_err!(
+                "Unexpected {} in expression: {}",
+                token,
+                s
+            )),
+        }
+    }
+}
+
+impl Expr {
+    /// evaluate against a record, columns missing from the record are null
+    pub fn eval(&self, record: &Record) -> Result<Value> {
+        match self {
+            Expr::Literal(value) => Ok(value.clone()),
+            Expr::Column(name) => Ok(record.get(name).cloned().unwrap_or(Value::Null)),
+            Expr::Call(name, args) => {
+                let args = args
+                    .iter()
+                    .map(|arg| arg.eval(record))
+                    .collect::<Result<Vec<_>>>()?;
+                functions::call(name, &args)
+            }
+            Expr::Unary(op, expr) => {
+                let value = expr.eval(record)?;
+                match op {
+                    UnaryOp::Not => Ok(Value::Bool(!truthy(&value))),
+                    UnaryOp::Neg => arithmetic(BinaryOp::Sub, &Value::from(0), &value),
+                }
+            }
+            Expr::Binary(BinaryOp::And, lhs, rhs) => {
+                let ret = truthy(&lhs.eval(record)?) && truthy(&rhs.eval(record)?);
+                Ok(Value::Bool(ret))
+            }
+            Expr::Binary(BinaryOp::Or, lhs, rhs) => {
+                let ret = truthy(&lhs.eval(record)?) || truthy(&rhs.eval(record)?);
+                Ok(Value::Bool(ret))
+            }
+            Expr::Binary(op, lhs, rhs) => {
+                // `Code == "007"` compares as text, `Code == 7` as numbers
+                let textual = lhs.is_string_literal() || rhs.is_string_literal();
+                let lhs = lhs.eval(record)?;
+                let rhs = rhs.eval(record)?;
+                match op {
+                    BinaryOp::Eq => Ok(Value::Bool(equals(&lhs, &rhs, textual))),
+                    BinaryOp::Ne => Ok(Value::Bool(!equals(&lhs, &rhs, textual))),
+                    BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge => {
+                        let ord = compare(&lhs, &rhs, textual);
+                        let ret = match op {
+                            BinaryOp::Lt => ord == Some(Ordering::Less),
+                            BinaryOp::Le => matches!(ord, Some(Ordering::Less | Ordering::Equal)),
+                            BinaryOp::Gt => ord == Some(Ordering::Greater),
+                            _ => matches!(ord, Some(Ordering::Greater | Ordering::Equal)),
+                        };
+                        Ok(Value::Bool(ret))
+                    }
+                    _ => arithmetic(*op, &lhs, &rhs),
+                }
+            }
+        }
+    }
+
+    /// evaluate as a condition
+    pub fn matches(&self, record: &Record) -> Result<bool> {
+        Ok(truthy(&self.eval(record)?))
+    }
+
+    fn is_string_literal(&self) -> bool {
+        matches!(self, Expr::Literal(Value::String(_)))
+    }
+}
+
+/// null, false, zero and empty strings are false, everything else is true
+pub fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64() != Some(0.0),
+        Value::String(s) => !s.is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        Value::Object(map) => !map.is_empty(),
+    }
+}
+
+/// numbers and numeric strings compare as numbers unless `textual`, other strings lexically,
+/// null compares to nothing
+fn compare(lhs: &Value, rhs: &Value, textual: bool) -> Option<Ordering> {
+    match (lhs, rhs) {
+        (Value::Null, _) | (_, Value::Null) => None,
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        _ if textual => Some(as_text(lhs).cmp(&as_text(rhs))),
+        _ => match (as_number(lhs), as_number(rhs)) {
+            (Some(a), Some(b)) => a.partial_cmp(&b),
+            _ => Some(as_text(lhs).cmp(&as_text(rhs))),
+        },
+    }
+}
+
+fn equals(lhs: &Value, rhs: &Value, textual: bool) -> bool {
+    match (lhs, rhs) {
+        (Value::Null, Value::Null) => true,
+        (Value::Null, _) | (_, Value::Null) => false,
+        _ => compare(lhs, rhs, textual) == Some(Ordering::Equal),
+    }
+}
+
+pub fn as_number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => {
+            let s = s.trim();
+            if !s.bytes().any(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            s.parse::<f64>().ok().filter(|f| f.is_finite())
+        }
+        _ => None,
+    }
+}
+
+fn as_integer(value: &Value) -> Option<i64> {
+    match value {
+        Value::Number(n) => n.as_i64(),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+pub fn as_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        _ => value.to_string(),
+    }
+}
+
+/// integer arithmetic where it is exact, float otherwise; `+` concatenates non-numeric strings
+fn arithmetic(op: BinaryOp, lhs: &Value, rhs: &Value) -> Result<Value> {
+    if lhs.is_null() || rhs.is_null() {
+        return Ok(Value::Null);
+    }
+    if let (Some(a), Some(b)) = (as_integer(lhs), as_integer(rhs)) {
+        let ret = match op {
+            BinaryOp::Add => a.checked_add(b),
+            BinaryOp::Sub => a.checked_sub(b),
+            BinaryOp::Mul => a.checked_mul(b),
+            BinaryOp::Div if a.checked_rem(b) == Some(0) => a.checked_div(b),
+            BinaryOp::Rem => a.checked_rem(b),
+            _ => None,
+        };
+        if let Some(ret) = ret {
+            return Ok(Value::from(ret));
+        }
+    }
+    match (as_number(lhs), as_number(rhs)) {
+        (Some(a), Some(b)) => {
+            let ret = match op {
+                BinaryOp::Add => a + b,
+                BinaryOp::Sub => a - b,
+                BinaryOp::Mul => a * b,
+                BinaryOp::Div => a / b,
+                _ => a % b,
+            };
+            Ok(Number::from_f64(ret).map_or(Value::Null, Value::Number))
+        }
+        _ if op == BinaryOp::Add => Ok(Value::String(as_text(lhs) + &as_text(rhs))),
+        _ => Err(anyhow::format_err!(
+            "Cannot apply {} to {} and {}",
+            op,
+            lhs,
+            rhs
+        )),
+    }
+}
+
+impl fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            BinaryOp::Or => "||",
+            BinaryOp::And => "&&",
+            BinaryOp::Eq => "==",
+            BinaryOp::Ne => "!=",
+            BinaryOp::Lt => "<",
+            BinaryOp::Le => "<=",
+            BinaryOp::Gt => ">",
+            BinaryOp::Ge => ">=",
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Rem => "%",
+        };
+        write!(f, "{}", op)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Literal(Value),
+    Ident(String),
+    Op(&'static str),
+    LParen,
+    RParen,
+    Comma,
+    End,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Literal(value) => write!(f, "{}", value),
+            Token::Ident(name) => write!(f, "`{}`", name),
+            Token::Op(op) => write!(f, "`{}`", op),
+            Token::LParen => write!(f, "`(`"),
+            Token::RParen => write!(f, "`)`"),
+            Token::Comma => write!(f, "`,`"),
+            Token::End => write!(f, "end of input"),
+        }
+    }
+}
+
+const OPERATORS: [&str; 15] = [
+    "&&", "||", "==", "!=", "<=", ">=", "<", ">", "=", "!", "+", "-", "*", "/", "%",
+];
+
+fn tokenize(s: &str) -> Result<Vec<Token>> {
+    let chars = s.chars().collect::<Vec<_>>();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '(' {
+            tokens.push(Token::LParen);
+            i += 1;
+        } else if c == ')' {
+            tokens.push(Token::RParen);
+            i += 1;
+        } else if c == ',' {
+            tokens.push(Token::Comma);
+            i += 1;
+        } else if c == '"' || c == '\'' || c == '`' {
+            let (text, end) = read_quoted(&chars, i)?;
+            tokens.push(if c == '`' {
+                Token::Ident(text)
+            } else {
+                Token::Literal(Value::String(text))
+            });
+            i = end;
+        } else if c.is_ascii_digit()
+            || (c == '.' && chars.get(i + 1).is_some_and(char::is_ascii_digit))
+        {
+            let start = i;
+            // the sign of an exponent like `1e-5`
+            let exponent_sign = |i: usize| {
+                (chars[i] == '-' || chars[i] == '+')
+                    && matches!(chars[i - 1], 'e' | 'E')
+                    && chars.get(i + 1).is_some_and(char::is_ascii_digit)
+            };
+            while i < chars.len()
+                && (chars[i].is_ascii_alphanumeric() || chars[i] == '.' || exponent_sign(i))
+            {
+                i += 1;
+            }
+            let text = chars[start..i].iter().collect::<String>();
+            let value = match text.parse::<i64>() {
+                Ok(n) => Value::from(n),
+                Err(_) => text
+                    .parse::<f64>()
+                    .ok()
+                    .and_then(Number::from_f64)
+                    .map(Value::Number)
+                    .ok_or_else(|| anyhow::format_err!("Invalid number: {}", text))?,
+            };
+            tokens.push(Token::Literal(value));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len()
+                && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '.')
+            {
+                i += 1;
+            }
+            let word = chars[start..i].iter().collect::<String>();
+            tokens.push(match word.as_str() {
+                "true" => Token::Literal(Value::Bool(true)),
+                "false" => Token::Literal(Value::Bool(false)),
+                "null" => Token::Literal(Value::Null),
+                "and" => Token::Op("&&"),
+                "or" => Token::Op("||"),
+                "not" => Token::Op("!"),
+                _ => Token::Ident(word),
+            });
+        } else {
+            let rest = chars[i..].iter().take(2).collect::<String>();
+            let op = OPERATORS
+                .iter()
+                .find(|op| rest.starts_with(*op))
+                .ok_or_else(|| anyhow::format_err!("Unexpected character {:?} in expression", c))?;
+            tokens.push(Token::Op(op));
+            i += op.len();
+        }
+    }
+    tokens.push(Token::End);
+    Ok(tokens)
+}
+
+/// read a quoted string starting at `start`, a backslash escapes the next character
+fn read_quoted(chars: &[char], start: usize) -> Result<(String, usize)> {
+    let quote = chars[start];
+    let mut text = String::new();
+    let mut i = start + 1;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if i + 1 < chars.len() => {
+                text.push(chars[i + 1]);
+                i += 2;
+            }
+            c if c == quote => return Ok((text, i + 1)),
+            c => {
+                text.push(c);
+                i += 1;
+            }
+        }
+    }
+    Err(anyhow::format_err!("Unterminated {} in expression", quote))
+}
+
+/// how deep `!`, `-`, parentheses and function calls may nest before parsing gives up
+const MAX_DEPTH: usize = 128;
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos]
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.pos].clone();
+        if token != Token::End {
+            self.pos += 1;
+        }
+        token
+    }
+
+    /// consume the next token if it is one of the given operators
+    fn eat_op(&mut self, ops: &[&str]) -> Option<&'static str> {
+        match self.peek() {
+            Token::Op(op) if ops.contains(op) => {
+                let op = *op;
+                self.pos += 1;
+                Some(op)
+            }
+            _ => None,
+        }
+    }
+
+    /// run a parse step one nesting level deeper, so hostile input can't overflow the stack
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        if self.depth >= MAX_DEPTH {
+            return Err(anyhow::format_err!(
+                "Expression nested more than {} levels deep",
+                MAX_DEPTH
+            ));
+        }
+        self.depth += 1;
+        let ret = parse(self);
+        self.depth -= 1;
+        ret
+    }
+
+    fn parse_or(&mut self) -> Result<Expr> {
+        let mut lhs = self.parse_and()?;
+        while self.eat_op(&["||"]).is_some() {
+            let rhs = self.parse_and()?;
+            lhs = Expr::Binary(BinaryOp::Or, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr> {
+        let mut lhs = self.parse_not()?;
+        while self.eat_op(&["&&"]).is_some() {
+            let rhs = self.parse_not()?;
+            lhs = Expr::Binary(BinaryOp::And, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_not(&mut self) -> Result<Expr> {
+        if self.eat_op(&["!"]).is_some() {
+            let expr = self.nested(Self::parse_not)?;
+            return Ok(Expr::Unary(UnaryOp::Not, Box::new(expr)));
+        }
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr> {
+        let lhs = self.parse_additive()?;
+        let op = match self.eat_op(&["==", "=", "!=", "<", "<=", ">", ">="]) {
+            Some("==" | "=") => BinaryOp::Eq,
+            Some("!=") => BinaryOp::Ne,
+            Some("<") => BinaryOp::Lt,
+            Some("<=") => BinaryOp::Le,
+            Some(">") => BinaryOp::Gt,
+            Some(_) => BinaryOp::Ge,
+            None => return Ok(lhs),
+        };
+        let rhs = self.parse_additive()?;
+        Ok(Expr::Binary(op, Box::new(lhs), Box::new(rhs)))
+    }
+
+    fn parse_additive(&mut self) -> Result<Expr> {
+        let mut lhs = self.parse_multiplicative()?;
+        while let Some(op) = self.eat_op(&["+", "-"]) {
+            let op = if op == "+" {
+                BinaryOp::Add
+            } else {
+                BinaryOp::Sub
+            };
+            let rhs = self.parse_multiplicative()?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_multiplicative(&mut self) -> Result<Expr> {
+        let mut lhs = self.parse_unary()?;
+        while let Some(op) = self.eat_op(&["*", "/", "%"]) {
+            let op = match op {
+                "*" => BinaryOp::Mul,
+                "/" => BinaryOp::Div,
+                _ => BinaryOp::Rem,
+            };
+            let rhs = self.parse_unary()?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr> {
+        if self.eat_op(&["-"]).is_some() {
+            let expr = self.nested(Self::parse_unary)?;
+            return Ok(Expr::Unary(UnaryOp::Neg, Box::new(expr)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr> {
+        match self.next() {
+            Token::Literal(value) => Ok(Expr::Literal(value)),
+            Token::Ident(name) if *self.peek() == Token::LParen => {
+                self.next();
+                let mut args = Vec::new();
+                if *self.peek() == Token::RParen {
+                    self.next();
+                } else {
+                    loop {
+                        args.push(self.nested(Self::parse_or)?);
+                        match self.next() {
+                            Token::Comma => {}
+                            Token::RParen => break,
+                            token => {
+                                return Err(anyhow::format_err!(
+                                    "Expected `,` or `)`, found {}",
+                                    token
+                                ))
+                            }
+                        }
+                    }
+                }
+                functions::check(&name, args.len())?;
+                Ok(Expr::Call(name, args))
+            }
+            Token::Ident(name) => Ok(Expr::Column(name)),
+            Token::LParen => {
+                let expr = self.nested(Self::parse_or)?;
+                match self.next() {
+                    Token::RParen => Ok(expr),
+                    token => Err(anyhow::format_err!("Expected `)`, found {}", token)),
+                }
+            }
+            token => Err(anyhow::format_err!("Unexpected {} in expression", token)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn record(value: Value) -> Record {
+        value.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn test_parse_precedence() -> Result<()> {
+        let expr: Expr = "a || b && !c".parse()?;
+        let expected = Expr::Binary(
+            BinaryOp::Or,
+            Box::new(Expr::Column("a".into())),
+            Box::new(Expr::Binary(
+                BinaryOp::And,
+                Box::new(Expr::Column("b".into())),
+                Box::new(Expr::Unary(
+                    UnaryOp::Not,
+                    Box::new(Expr::Column("c".into())),
+                )),
+            )),
+        );
+        assert_eq!(expr, expected);
+
+        assert!("a ==".parse::<Expr>().is_err());
+        assert!("(a".parse::<Expr>().is_err());
+        assert!("a b".parse::<Expr>().is_err());
+
+        let nested = |open: &str, close: &str, n: usize| open.repeat(n) + "a" + &close.repeat(n);
+        assert!(nested("(", ")", MAX_DEPTH).parse::<Expr>().is_ok());
+        assert!(nested("(", ")", MAX_DEPTH + 1).parse::<Expr>().is_err());
+        assert!(nested("!", "", 100_000).parse::<Expr>().is_err());
+        assert!(nested("-", "", 100_000).parse::<Expr>().is_err());
+        assert!(nested("upper(", ")", 100_000).parse::<Expr>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_filter() -> Result<()> {
+        let expr: Expr = r#"Position == "Goalkeeper" && Age > 30"#.parse()?;
+        assert!(expr.matches(&record(json!({"Position": "Goalkeeper", "Age": "35"})))?);
+        assert!(!expr.matches(&record(json!({"Position": "Goalkeeper", "Age": 29})))?);
+        assert!(!expr.matches(&record(json!({"Position": "Defender", "Age": 35})))?);
+
+        let expr: Expr = "`Kit Number` >= 10 and not (Name = 'x')".parse()?;
+        assert!(expr.matches(&record(json!({"Kit Number": "10", "Name": "y"})))?);
+        assert!(!expr.matches(&record(json!({"Kit Number": null, "Name": "y"})))?);
+
+        let r = record(json!({"Code": "007", "Age": "35"}));
+        let eval = |s: &str| s.parse::<Expr>().and_then(|e| e.matches(&r));
+        assert!(eval(r#"Code == "007""#)?);
+        assert!(!eval(r#"Code == "7""#)?);
+        assert!(eval("Code == 7")?);
+        assert!(eval(r#"Age > "100""#)?);
+        assert!(!eval("Age > 100")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_arithmetic() -> Result<()> {
+        let r = record(json!({"a": "6", "b": 4, "s": "x"}));
+        let eval = |s: &str| s.parse::<Expr>().and_then(|e| e.eval(&r));
+        assert_eq!(eval("a * b - 1")?, json!(23));
+        assert_eq!(eval("a / b")?, json!(1.5));
+        assert_eq!(eval("-a % b")?, json!(-2));
+        assert_eq!(eval("s + a")?, json!("x6"));
+        assert_eq!(eval("missing + 1")?, Value::Null);
+        assert_eq!(eval("1e-5")?, json!(0.00001));
+        assert_eq!(eval("2.5E+3 - b")?, json!(2496.0));
+        assert_eq!(eval("b-1")?, json!(3));
+        assert!(eval("s * 2").is_err());
+
+        let r = record(json!({"m": i64::MIN}));
+        let eval = |s: &str| s.parse::<Expr>().and_then(|e| e.eval(&r));
+        assert_eq!(eval("m / -1")?, json!(-(i64::MIN as f64)));
+        assert_eq!(eval("m % -1")?, json!(0.0));
+        assert_eq!(eval("m % 0")?, Value::Null);
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_call() -> Result<()> {
+        let r = record(json!({"Name": " buffon ", "Weight": "82", "Height": "1.92"}));
+        let eval = |s: &str| s.parse::<Expr>().and_then(|e| e.eval(&r));
+        assert_eq!(eval("upper(trim(Name))")?, json!("BUFFON"));
+        assert_eq!(eval("round(Weight / (Height * Height), 1)")?, json!(22.2));
+        assert_eq!(
+            eval(r#"concat(trim(Name), "-", len(Name))"#)?,
+            json!("buffon-8")
+        );
+        assert!(eval("upper(Name").is_err());
+        assert!(eval("shout(Name)").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,229 @@
+use anyhow::Result;
+use serde_json::{Map, Value};
+
+use crate::cli::csv_options::ArrayMode;
+
+/// how far past the end of an array an index may point, the gap is filled with nulls
+const MAX_INDEX_GAP: usize = 1000;
+
+/// flatten a record into `(column, cell)` pairs, nested objects use dotted keys like `address.city`
+pub fn flatten(record: &Map<String, Value>, arrays: ArrayMode) -> Vec<(String, String)> {
+    flatten_values(record, arrays)
+        .into_iter()
+        .map(|(key, value)| (key, to_cell(&value)))
+        .collect()
+}
+
+/// like `flatten`, but scalar leaves keep their JSON type
+pub fn flatten_values(record: &Map<String, Value>, arrays: ArrayMode) -> Vec<(String, Value)> {
+    let mut ret = Vec::with_capacity(record.len());
+    for (key, value) in record {
+        flatten_value(key.clone(), value, arrays, &mut ret);
+    }
+    ret
+}
+
+fn flatten_value(key: String, value: &Value, arrays: ArrayMode, ret: &mut Vec<(String, Value)>) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (k, v) in map {
+                flatten_value(format!("{}.{}", key, k), v, arrays, ret);
+            }
+        }
+        Value::Array(items) if !items.is_empty() => match arrays {
+            ArrayMode::Index => {
+                for (i, v) in items.iter().enumerate() {
+                    flatten_value(format!("{}[{}]", key, i), v, arrays, ret);
+                }
+            }
+            ArrayMode::Join => {
+                let joined = items.iter().map(to_cell).collect::<Vec<_>>().join(";");
+                ret.push((key, joined.into()));
+            }
+            ArrayMode::Json => ret.push((key, value.to_string().into())),
+        },
+        Value::Object(_) | Value::Array(_) => ret.push((key, Value::Null)),
+        _ => ret.push((key, value.clone())),
+    }
+}
+
+/// render a single value as CSV cell text
+pub fn to_cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        Value::Object(map) if map.is_empty() => String::new(),
+        Value::Array(items) if items.is_empty() => String::new(),
+        _ => value.to_string(),
+    }
+}
+
+/// one step of a flattened column name
+#[derive(Debug, PartialEq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+/// the reverse of `flatten` with `ArrayMode::Index`, `a.b[1]` becomes `{"a": {"b": [null, v]}}`
+pub fn unflatten(record: Map<String, Value>) -> Result<Map<String, Value>> {
+    let mut root = Value::Object(Map::with_capacity(record.len()));
+    for (column, value) in record {
+        let path = parse_path(&column);
+        insert(&mut root, &path, value, &column)?;
+    }
+    match root {
+        Value::Object(map) => Ok(map),
+        _ => unreachable!("the root is always an object"),
+    }
+}
+
+/// split on dots and trailing `[n]` indices, anything else stays part of the key
+fn parse_path(column: &str) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    for part in column.split('.') {
+        let mut name = part;
+        let mut indices = Vec::new();
+        while let Some(rest) = name.strip_suffix(']') {
+            let Some(open) = rest.rfind('[') else {
+                break;
+            };
+            let Ok(index) = rest[open + 1..].parse::<usize>() else {
+                break;
+            };
+            indices.push(index);
+            name = &rest[..open];
+        }
+        if !name.is_empty() || indices.is_empty() {
+            segments.push(Segment::Key(name.to_string()));
+        }
+        segments.extend(indices.into_iter().rev().map(Segment::Index));
+    }
+    // records are objects, so a column starting with an index is just a key
+    if matches!(segments.first(), Some(Segment::Index(_))) {
+        return vec![Segment::Key(column.to_string())];
+    }
+    segments
+}
+
+fn insert(slot: &mut Value, path: &[Segment], value: Value, column: &str) -> Result<()> {
+    let conflict = || anyhow::format_err!("Column {} conflicts with another column", column);
+    let Some((first, rest)) = path.split_first() else {
+        match (&slot, &value) {
+            (Value::Null, _) => *slot = value,
+            // an empty parent cell next to its children, e.g. `address` and `address.city`
+            (_, Value::Null) => {}
+            _ => return Err(conflict()),
+        }
+        return Ok(());
+    };
+    match first {
+        Segment::Key(key) => {
+            if slot.is_null() {
+                *slot = Value::Object(Map::new());
+            }
+            let Value::Object(map) = slot else {
+                return Err(conflict());
+            };
+            let child = map.entry(key.clone()).or_insert(Value::Null);
+            insert(child, rest, value, column)
+        }
+        Segment::Index(index) => {
+            if slot.is_null() {
+                *slot = Value::Array(Vec::new());
+            }
+            let Value::Array(items) = slot else {
+                return Err(conflict());
+            };
+            if *index > items.len() + MAX_INDEX_GAP {
+                return Err(anyhow::format_err!(
+                    "Column {} index {} is more than {} past the end of the array",
+                    column,
+                    index,
+                    MAX_INDEX_GAP
+                ));
+            }
+            if items.len() <= *index {
+                let len = index
+                    .checked_add(1)
+                    .ok_or_else(|| anyhow::format_err!("Column {} index overflows", column))?;
+                items.resize(len, Value::Null);
+            }
+            insert(&mut items[*index], rest, value, column)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_flatten_nested() {
+        let record = json!({
+            "name": "a",
+            "address": {"city": "Turin", "zip": null},
+            "tags": ["x", 1],
+            "active": true,
+        });
+        let record = record.as_object().unwrap();
+
+        let flat = flatten(record, ArrayMode::Index);
+        let expected = vec![
+            ("name", "a"),
+            ("address.city", "Turin"),
+            ("address.zip", ""),
+            ("tags[0]", "x"),
+            ("tags[1]", "1"),
+            ("active", "true"),
+        ];
+        let expected = expected
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(flat, expected);
+
+        let flat = flatten(record, ArrayMode::Join);
+        assert_eq!(flat[3], ("tags".to_string(), "x;1".to_string()));
+        let flat = flatten(record, ArrayMode::Json);
+        assert_eq!(flat[3], ("tags".to_string(), r#"["x",1]"#.to_string()));
+    }
+
+    #[test]
+    fn test_unflatten_round_trip() -> Result<()> {
+        let record = json!({
+            "name": "a",
+            "address": {"city": "Turin", "geo": {"lat": 45.07}},
+            "tags": ["x", {"id": 1}],
+            "matrix": [[1, 2], [3]],
+        });
+        let record = record.as_object().unwrap();
+        let flat = flatten_values(record, ArrayMode::Index)
+            .into_iter()
+            .collect::<Map<_, _>>();
+        assert!(flat.contains_key("tags[1].id"));
+        assert!(flat.contains_key("matrix[0][1]"));
+        assert_eq!(&unflatten(flat)?, record);
+
+        let sparse = json!({"tags[2]": "z", "Kit Number": "1", "odd[x]": "y"});
+        let nested = unflatten(sparse.as_object().unwrap().clone())?;
+        assert_eq!(
+            Value::Object(nested),
+            json!({"tags": [null, null, "z"], "Kit Number": "1", "odd[x]": "y"})
+        );
+
+        let clash = json!({"a": "1", "a.b": "2"});
+        assert!(unflatten(clash.as_object().unwrap().clone()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_unflatten_huge_index() {
+        for column in ["a[18446744073709551615]", "a[100000000000]"] {
+            let mut record = Map::new();
+            record.insert(column.to_string(), json!("x"));
+            assert!(unflatten(record).is_err());
+        }
+    }
+}
@@ -0,0 +1,253 @@
+use anyhow::Result;
+use chrono::{NaiveDate, NaiveDateTime};
+use regex::Regex;
+use serde_json::{Number, Value};
+use std::{cell::RefCell, collections::HashMap};
+
+use super::expr::{as_number, as_text};
+use crate::utils;
+
+/// `(name, min args, max args)` of every built-in function
+const FUNCTIONS: [(&str, usize, usize); 19] = [
+    ("upper", 1, 1),
+    ("lower", 1, 1),
+    ("trim", 1, 1),
+    ("len", 1, 1),
+    ("substr", 2, 3),
+    ("replace", 3, 3),
+    ("regex_extract", 2, 3),
+    ("regex_replace", 3, 3),
+    ("concat", 1, usize::MAX),
+    ("coalesce", 1, usize::MAX),
+    ("int", 1, 1),
+    ("float", 1, 1),
+    ("str", 1, 1),
+    ("round", 1, 2),
+    ("abs", 1, 1),
+    ("floor", 1, 1),
+    ("ceil", 1, 1),
+    ("date_parse", 2, 2),
+    ("date_format", 2, 2),
+];
+
+thread_local! {
+    /// patterns are usually literals, so each is compiled once rather than per record
+    static REGEXES: RefCell<HashMap<String, Regex>> = RefCell::new(HashMap::new());
+}
+
+/// check a call against the function table when the expression is parsed
+pub fn check(name: &str, args: usize) -> Result<()> {
+    let (_, min, max) = FUNCTIONS
+        .iter()
+        .find(|(n, _, _)| *n == name)
+        .ok_or_else(|| anyhow::format_err!("Unknown function: {}", name))?;
+    if args < *min || args > *max {
+        return Err(anyhow::format_err!(
+            "Wrong number of arguments to {}: {}",
+            name,
+            args
+        ));
+    }
+    Ok(())
+}
+
+/// call a built-in function, most of them return null for a null first argument
+pub fn call(name: &str, args: &[Value]) -> Result<Value> {
+    if args.first().is_some_and(Value::is_null) && !matches!(name, "concat" | "coalesce") {
+        return Ok(Value::Null);
+    }
+    let text = |i: usize| args.get(i).map(as_text).unwrap_or_default();
+    let ret = match name {
+        "upper" => text(0).to_uppercase().into(),
+        "lower" => text(0).to_lowercase().into(),
+        "trim" => text(0).trim().into(),
+        "len" => text(0).chars().count().into(),
+        "substr" => {
+            let start = integer_arg(args, 1)?.max(0) as usize;
+            let value = text(0);
+            let chars = value.chars().skip(start);
+            match args.get(2) {
+                Some(_) => chars.take(integer_arg(args, 2)?.max(0) as usize).collect(),
+                None => chars.collect::<String>(),
+            }
+            .into()
+        }
+        "replace" => text(0).replace(&text(1), &text(2)).into(),
+        "regex_extract" => {
+            let group = match args.get(2) {
+                Some(_) => integer_arg(args, 2)?.max(0) as usize,
+                None => 0,
+            };
+            with_regex(&text(1), |re| {
+                re.captures(&text(0))
+                    .and_then(|c| c.get(group))
+                    .map_or(Value::Null, |m| m.as_str().into())
+            })?
+        }
+        "regex_replace" => with_regex(&text(1), |re| {
+            re.replace_all(&text(0), text(2).as_str())
+                .into_owned()
+                .into()
+        })?,
+        "concat" => args.iter().map(as_text).collect::<String>().into(),
+        "coalesce" => args
+            .iter()
+            .find(|v| !v.is_null())
+            .cloned()
+            .unwrap_or(Value::Null),
+        "int" => as_number(&args[0]).map_or(Value::Null, |n| (n.trunc() as i64).into()),
+        "float" => as_number(&args[0]).map_or(Value::Null, float),
+        "str" => text(0).into(),
+        "round" => {
+            let digits = match args.get(1) {
+                Some(_) => integer_arg(args, 1)?,
+                None => 0,
+            };
+            match as_number(&args[0]) {
+                Some(n) if digits <= 0 => (n.round() as i64).into(),
+                Some(n) => {
+                    let scale = 10f64.powi(digits as i32);
+                    float((n * scale).round() / scale)
+                }
+                None => Value::Null,
+            }
+        }
+        "abs" => numeric(&args[0], f64::abs),
+        "floor" => numeric(&args[0], f64::floor),
+        "ceil" => numeric(&args[0], f64::ceil),
+        "date_parse" => parse_date(&text(0), &text(1)).unwrap_or(Value::Null),
+        "date_format" => format_date(&text(0), &text(1))?,
+        _ => return Err(anyhow::format_err!("Unknown function: {}", name)),
+    };
+    Ok(ret)
+}
+
+fn integer_arg(args: &[Value], index: usize) -> Result<i64> {
+    args.get(index)
+        .and_then(as_number)
+        .map(|n| n as i64)
+        .ok_or_else(|| anyhow::format_err!("Argument {} must be a number", index + 1))
+}
+
+fn float(n: f64) -> Value {
+    Number::from_f64(n).map_or(Value::Null, Value::Number)
+}
+
+/// integers stay integers, e.g. `abs(-3)` is `3` and not `3.0`
+fn numeric(value: &Value, f: fn(f64) -> f64) -> Value {
+    match as_number(value) {
+        Some(n) => {
+            let ret = f(n);
+            if ret.fract() == 0.0 && ret.abs() < 1e15 {
+                (ret as i64).into()
+            } else {
+                float(ret)
+            }
+        }
+        None => Value::Null,
+    }
+}
+
+fn with_regex(pattern: &str, f: impl FnOnce(&Regex) -> Value) -> Result<Value> {
+    REGEXES.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        if !cache.contains_key(pattern) {
+            let re = Regex::new(pattern)
+                .map_err(|e| anyhow::format_err!("Invalid regex {}: {}", pattern, e))?;
+            cache.insert(pattern.to_string(), re);
+        }
+        Ok(f(&cache[pattern]))
+    })
+}
+
+/// parse with a chrono format into ISO 8601, `2024-03-01` or `2024-03-01T09:30:00`
+fn parse_date(value: &str, format: &str) -> Option<Value> {
+    let value = value.trim();
+    if let Ok(dt) = NaiveDateTime::parse_from_str(value, format) {
+        return Some(dt.format("%Y-%m-%dT%H:%M:%S").to_string().into());
+    }
+    let date = NaiveDate::parse_from_str(value, format).ok()?;
+    Some(date.format("%Y-%m-%d").to_string().into())
+}
+
+/// format an ISO 8601 date or date time with a chrono format, null if it isn't one
+fn format_date(value: &str, format: &str) -> Result<Value> {
+    let value = value.trim();
+    let dt = ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"]
+        .iter()
+        .find_map(|f| NaiveDateTime::parse_from_str(value, f).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .ok()
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+        });
+    let Some(dt) = dt else {
+        return Ok(Value::Null);
+    };
+    Ok(utils::format_date(dt.format(format), format)?.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn run(name: &str, args: Vec<Value>) -> Result<Value> {
+        check(name, args.len())?;
+        call(name, &args)
+    }
+
+    #[test]
+    fn test_string_functions() -> Result<()> {
+        assert_eq!(run("upper", vec![json!("Buffon")])?, json!("BUFFON"));
+        assert_eq!(run("trim", vec![json!("  a ")])?, json!("a"));
+        assert_eq!(run("len", vec![json!("Málaga")])?, json!(6));
+        assert_eq!(
+            run("substr", vec![json!("Juventus"), json!(0), json!(4)])?,
+            json!("Juve")
+        );
+        assert_eq!(
+            run(
+                "regex_extract",
+                vec![json!("Apr 18, 1990 (29)"), json!(r"\((\d+)\)"), json!(1)]
+            )?,
+            json!("29")
+        );
+        assert_eq!(
+            run(
+                "regex_replace",
+                vec![json!("a-b-c"), json!("-"), json!("_")]
+            )?,
+            json!("a_b_c")
+        );
+        assert_eq!(run("coalesce", vec![Value::Null, json!(2)])?, json!(2));
+        assert_eq!(run("upper", vec![Value::Null])?, Value::Null);
+        assert!(run("nope", vec![]).is_err());
+        assert!(run("upper", vec![json!("a"), json!("b")]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_number_and_date_functions() -> Result<()> {
+        assert_eq!(run("round", vec![json!("2.346"), json!(2)])?, json!(2.35));
+        assert_eq!(run("round", vec![json!(2.5)])?, json!(3));
+        assert_eq!(run("abs", vec![json!(-3)])?, json!(3));
+        assert_eq!(run("int", vec![json!("n/a")])?, Value::Null);
+        assert_eq!(
+            run(
+                "date_parse",
+                vec![json!("Apr 18, 1990"), json!("%b %d, %Y")]
+            )?,
+            json!("1990-04-18")
+        );
+        assert_eq!(
+            run("date_format", vec![json!("1990-04-18"), json!("%Y")])?,
+            json!("1990")
+        );
+        assert_eq!(
+            run("date_parse", vec![json!("garbage"), json!("%Y")])?,
+            Value::Null
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,168 @@
+use anyhow::Result;
+use csv::StringRecord;
+use serde_json::{Number, Value};
+
+use crate::cli::csv_options::ColumnType;
+
+/// how the raw text of a column is turned into a JSON value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnTyping {
+    /// keep every cell as a string
+    Text,
+    /// guessed from a sample, cells that don't fit fall back to strings
+    Inferred(ColumnType),
+    /// requested by the user, cells that don't fit are an error
+    Declared(ColumnType),
+}
+
+impl ColumnTyping {
+    pub fn convert(&self, value: &str) -> Result<Value> {
+        match self {
+            ColumnTyping::Text => Ok(Value::String(value.to_string())),
+            ColumnTyping::Inferred(ty) => {
+                Ok(parse_value(value, *ty).unwrap_or_else(|| Value::String(value.to_string())))
+            }
+            ColumnTyping::Declared(ty) => parse_value(value, *ty)
+                .ok_or_else(|| anyhow::format_err!("Cannot convert {:?} to {}", value, ty)),
+        }
+    }
+}
+
+/// accumulates which types every non-empty value of a column fits
+#[derive(Debug, Clone)]
+pub struct TypeGuess {
+    integer: bool,
+    float: bool,
+    boolean: bool,
+    seen: usize,
+    /// whether any empty cell was observed
+    empty: bool,
+}
+
+impl Default for TypeGuess {
+    fn default() -> Self {
+        Self {
+            integer: true,
+            float: true,
+            boolean: true,
+            seen: 0,
+            empty: false,
+        }
+    }
+}
+
+impl TypeGuess {
+    pub fn observe(&mut self, value: &str) {
+        let value = value.trim();
+        if value.is_empty() {
+            self.empty = true;
+            return;
+        }
+        self.seen += 1;
+        self.integer = self.integer && value.parse::<i64>().is_ok();
+        self.float = self.float && parse_float(value).is_some();
+        self.boolean = self.boolean && parse_bool(value, false).is_some();
+    }
+
+    /// whether the column can be null, i.e. had empty cells
+    pub fn nullable(&self) -> bool {
+        self.empty
+    }
+
+    /// the narrowest type that fits every observed value, columns with only empty cells are strings
+    pub fn column_type(&self) -> ColumnType {
+        match self {
+            TypeGuess { seen: 0, .. } => ColumnType::String,
+            TypeGuess { integer: true, .. } => ColumnType::Integer,
+            TypeGuess { float: true, .. } => ColumnType::Float,
+            TypeGuess { boolean: true, .. } => ColumnType::Boolean,
+            _ => ColumnType::String,
+        }
+    }
+}
+
+/// infer the type of each of the `width` columns from the sampled records
+pub fn infer_types(records: &[StringRecord], width: usize) -> Vec<ColumnType> {
+    let mut guesses = vec![TypeGuess::default(); width];
+    for record in records {
+        for (guess, value) in guesses.iter_mut().zip(record.iter()) {
+            guess.observe(value);
+        }
+    }
+    guesses.iter().map(TypeGuess::column_type).collect()
+}
+
+/// parse a cell as the given type, empty cells become null
+pub fn parse_value(value: &str, ty: ColumnType) -> Option<Value> {
+    let trimmed = value.trim();
+    if trimmed.is_empty() && ty != ColumnType::String {
+        return Some(Value::Null);
+    }
+    match ty {
+        ColumnType::String => Some(Value::String(value.to_string())),
+        ColumnType::Integer => trimmed.parse::<i64>().ok().map(Value::from),
+        ColumnType::Float => parse_float(trimmed)
+            .and_then(Number::from_f64)
+            .map(Value::Number),
+        ColumnType::Boolean => parse_bool(trimmed, true).map(Value::Bool),
+    }
+}
+
+fn parse_float(value: &str) -> Option<f64> {
+    // reject `inf`, `NaN` and friends which f64 would happily accept
+    if !value.bytes().any(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    value.parse::<f64>().ok().filter(|f| f.is_finite())
+}
+
+/// `lenient` also accepts yes/no and 1/0, which are too ambiguous to infer from
+fn parse_bool(value: &str, lenient: bool) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "true" => Some(true),
+        "false" => Some(false),
+        "yes" | "y" | "1" if lenient => Some(true),
+        "no" | "n" | "0" if lenient => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_infer_types() {
+        let records = vec![
+            StringRecord::from(vec!["1", "1.5", "true", "x", ""]),
+            StringRecord::from(vec!["2", "3", "FALSE", "4", ""]),
+            StringRecord::from(vec!["", "", "", "", ""]),
+        ];
+        let types = infer_types(&records, 5);
+        assert_eq!(
+            types,
+            vec![
+                ColumnType::Integer,
+                ColumnType::Float,
+                ColumnType::Boolean,
+                ColumnType::String,
+                ColumnType::String,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_column_typing_convert() -> Result<()> {
+        let int = ColumnTyping::Inferred(ColumnType::Integer);
+        assert_eq!(int.convert("42")?, Value::from(42));
+        assert_eq!(int.convert("")?, Value::Null);
+        assert_eq!(int.convert("n/a")?, Value::from("n/a"));
+
+        let declared = ColumnTyping::Declared(ColumnType::Boolean);
+        assert_eq!(declared.convert("yes")?, Value::Bool(true));
+        assert!(declared.convert("maybe").is_err());
+
+        assert_eq!(parse_value("inf", ColumnType::Float), None);
+        Ok(())
+    }
+}
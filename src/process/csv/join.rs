@@ -0,0 +1,239 @@
+use anyhow::Result;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+
+use super::{expr, read_text_records, source::Record, writer};
+use crate::{
+    cli::csv_options::{CsvJoinOptions, JoinKind},
+    utils::get_writer,
+};
+
+/// which right columns end up in the output and under what name
+struct Layout {
+    /// `(left key, right key)`
+    keys: Vec<(String, String)>,
+    left: Vec<String>,
+    /// `(right column, output name)`, right keys are left out
+    right: Vec<(String, String)>,
+}
+
+impl Layout {
+    fn try_new(keys: Vec<(String, String)>, left: Vec<String>, right: Vec<String>) -> Result<Self> {
+        for (l, r) in &keys {
+            if !left.contains(l) {
+                return Err(anyhow::format_err!("Key column {} not in left file", l));
+            }
+            if !right.contains(r) {
+                return Err(anyhow::format_err!("Key column {} not in right file", r));
+            }
+        }
+        let mut names = left.iter().cloned().collect::<HashSet<_>>();
+        let right = right
+            .into_iter()
+            .filter(|column| keys.iter().all(|(_, r)| r != column))
+            .map(|column| {
+                let name = if left.contains(&column) {
+                    format!("{}_right", column)
+                } else {
+                    column.clone()
+                };
+                if !names.insert(name.clone()) {
+                    return Err(anyhow::format_err!(
+                        "Right column {} would be written as {}, which is already taken",
+                        column,
+                        name
+                    ));
+                }
+                Ok((column, name))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { keys, left, right })
+    }
+
+    /// output record for a left and/or right row, missing sides are null
+    fn combine(&self, left: Option<&Record>, right: Option<&Record>) -> Record {
+        let mut record = Record::with_capacity(self.left.len() + self.right.len());
+        for column in &self.left {
+            let value = match (left, self.right_key(column)) {
+                (Some(left), _) => left.get(column).cloned(),
+                // unmatched right rows still carry their key values
+                (None, Some(key)) => right.and_then(|r| r.get(key).cloned()),
+                (None, None) => None,
+            };
+            record.insert(column.clone(), value.unwrap_or(Value::Null));
+        }
+        for (column, name) in &self.right {
+            let value = right.and_then(|r| r.get(column).cloned());
+            record.insert(name.clone(), value.unwrap_or(Value::Null));
+        }
+        record
+    }
+
+    fn right_key(&self, left_column: &str) -> Option<&str> {
+        self.keys
+            .iter()
+            .find(|(l, _)| l == left_column)
+            .map(|(_, r)| r.as_str())
+    }
+}
+
+pub fn process_csv_join(options: &CsvJoinOptions) -> Result<()> {
+    let keys = options.on.iter().map(|s| parse_key(s)).collect();
+    let (left_headers, left) = read_text_records(&options.left, options.delimiter)?;
+    let (right_headers, right) = read_text_records(&options.right, options.delimiter)?;
+    let layout = Layout::try_new(keys, left_headers, right_headers)?;
+    let right = right.collect::<Result<Vec<_>>>()?;
+
+    let mut writer = writer::new_writer(
+        options.format,
+        get_writer(&options.output)?,
+        Default::default(),
+    );
+    join(&layout, left, &right, options.how, |record| {
+        writer.write_record(&record)
+    })?;
+    writer.finish()
+}
+
+/// `column` or `left_column=right_column`
+fn parse_key(s: &str) -> (String, String) {
+    match s.split_once('=') {
+        Some((l, r)) => (l.trim().to_string(), r.trim().to_string()),
+        None => (s.trim().to_string(), s.trim().to_string()),
+    }
+}
+
+/// hash join, the right side is indexed and the left side streamed
+fn join(
+    layout: &Layout,
+    left: impl Iterator<Item = Result<Record>>,
+    right: &[Record],
+    how: JoinKind,
+    mut emit: impl FnMut(Record) -> Result<()>,
+) -> Result<()> {
+    let key_of = |record: &Record, left_side: bool| {
+        layout
+            .keys
+            .iter()
+            .map(|(l, r)| {
+                let column = if left_side { l } else { r };
+                record.get(column).map(expr::as_text).unwrap_or_default()
+            })
+            .collect::<Vec<_>>()
+    };
+    let mut index: HashMap<Vec<String>, Vec<usize>> = HashMap::new();
+    for (i, record) in right.iter().enumerate() {
+        index.entry(key_of(record, false)).or_default().push(i);
+    }
+
+    let mut matched = vec![false; right.len()];
+    for record in left {
+        let record = record?;
+        match index.get(&key_of(&record, true)) {
+            Some(rows) => {
+                for i in rows {
+                    matched[*i] = true;
+                    emit(layout.combine(Some(&record), Some(&right[*i])))?;
+                }
+            }
+            None if how != JoinKind::Inner => emit(layout.combine(Some(&record), None))?,
+            None => {}
+        }
+    }
+
+    if how == JoinKind::Outer {
+        for (record, _) in right.iter().zip(matched).filter(|(_, m)| !m) {
+            emit(layout.combine(None, Some(record)))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn rows(values: Vec<Value>) -> Vec<Record> {
+        values
+            .into_iter()
+            .map(|v| v.as_object().unwrap().clone())
+            .collect()
+    }
+
+    fn run(how: JoinKind) -> Result<Vec<Value>> {
+        let layout = Layout::try_new(
+            vec![parse_key("id=player_id")],
+            vec!["id".into(), "name".into()],
+            vec!["player_id".into(), "name".into(), "goals".into()],
+        )?;
+        let left = rows(vec![
+            json!({"id": "1", "name": "a"}),
+            json!({"id": "2", "name": "b"}),
+        ]);
+        let right = rows(vec![
+            json!({"player_id": "1", "name": "A", "goals": "3"}),
+            json!({"player_id": "3", "name": "C", "goals": "1"}),
+            json!({"player_id": "1", "name": "A2", "goals": "4"}),
+        ]);
+        let mut output = Vec::new();
+        join(&layout, left.into_iter().map(Ok), &right, how, |r| {
+            output.push(Value::Object(r));
+            Ok(())
+        })?;
+        Ok(output)
+    }
+
+    #[test]
+    fn test_join_kinds() -> Result<()> {
+        let inner = run(JoinKind::Inner)?;
+        assert_eq!(
+            inner,
+            vec![
+                json!({"id": "1", "name": "a", "name_right": "A", "goals": "3"}),
+                json!({"id": "1", "name": "a", "name_right": "A2", "goals": "4"}),
+            ]
+        );
+
+        let left = run(JoinKind::Left)?;
+        assert_eq!(left.len(), 3);
+        assert_eq!(
+            left[2],
+            json!({"id": "2", "name": "b", "name_right": null, "goals": null})
+        );
+
+        let outer = run(JoinKind::Outer)?;
+        assert_eq!(outer.len(), 4);
+        assert_eq!(
+            outer[3],
+            json!({"id": "3", "name": null, "name_right": "C", "goals": "1"})
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_layout_missing_key() {
+        let layout = Layout::try_new(
+            vec![parse_key("id")],
+            vec!["id".into()],
+            vec!["other".into()],
+        );
+        assert!(layout.is_err());
+    }
+
+    #[test]
+    fn test_layout_name_collision() {
+        let layout = Layout::try_new(
+            vec![parse_key("id")],
+            vec!["id".into(), "name".into(), "name_right".into()],
+            vec!["id".into(), "name".into()],
+        );
+        assert!(layout.is_err());
+        let layout = Layout::try_new(
+            vec![parse_key("id")],
+            vec!["id".into(), "name".into()],
+            vec!["id".into(), "name".into(), "name_right".into()],
+        );
+        assert!(layout.is_err());
+    }
+}
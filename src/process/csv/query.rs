@@ -0,0 +1,357 @@
+use anyhow::Result;
+use serde_json::Value;
+use std::cmp::Ordering;
+
+use super::{
+    expr::{self, Expr},
+    source::{Record, Records},
+};
+use crate::cli::csv_options::CsvOptions;
+
+/// compute columns, then filter, sort, slice and project records, applied in that order
+#[derive(Debug, Default)]
+pub struct Query {
+    /// `--map` transforms first, then `--add`, so added columns see the mapped values
+    transforms: Vec<Transform>,
+    filter: Option<Expr>,
+    sort: Vec<SortKey>,
+    offset: usize,
+    limit: Option<usize>,
+    /// `(source column, output name)`
+    select: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Transform {
+    column: String,
+    expr: Expr,
+    /// `--add` appends a new column, `--map` replaces an existing one in place
+    append: bool,
+}
+
+impl Transform {
+    fn apply(&self, record: &mut Record) -> Result<()> {
+        let value = self.expr.eval(record)?;
+        match (record.get_mut(&self.column), self.append) {
+            (Some(slot), false) => *slot = value,
+            (None, true) => {
+                record.insert(self.column.clone(), value);
+            }
+            (Some(_), true) => {
+                return Err(anyhow::format_err!(
+                    "Column {} already exists, use --map to replace it",
+                    self.column
+                ))
+            }
+            (None, false) => {
+                return Err(anyhow::format_err!(
+                    "Unknown column {}, use --add to create it",
+                    self.column
+                ))
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SortKey {
+    column: String,
+    descending: bool,
+    ordering: SortOrdering,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SortOrdering {
+    /// numbers first in numeric order, then everything else in lexical order
+    Auto,
+    /// the same order as `Auto`, spelled out with `:num`
+    Numeric,
+    Lexical,
+}
+
+impl Query {
+    pub fn try_new(options: &CsvOptions) -> Result<Self> {
+        let maps = options.map.iter().map(|s| parse_transform(s, false));
+        let adds = options.add.iter().map(|s| parse_transform(s, true));
+        let transforms = maps.chain(adds).collect::<Result<_>>()?;
+        let filter = options.filter.as_deref().map(str::parse).transpose()?;
+        let sort = options
+            .sort_by
+            .iter()
+            .map(|s| parse_sort_key(s))
+            .collect::<Result<_>>()?;
+        let select = options.select.iter().map(|s| parse_selection(s)).collect();
+        Ok(Self {
+            transforms,
+            filter,
+            sort,
+            offset: options.offset,
+            limit: options.limit,
+            select,
+        })
+    }
+
+    /// wrap the records, only sorting needs to buffer them
+    pub fn apply(self, mut records: Records) -> Result<Records> {
+        let Query {
+            transforms,
+            filter,
+            sort,
+            offset,
+            limit,
+            select,
+        } = self;
+
+        if !transforms.is_empty() {
+            records = Box::new(records.map(move |record| {
+                let mut record = record?;
+                for transform in &transforms {
+                    transform.apply(&mut record)?;
+                }
+                Ok(record)
+            }));
+        }
+
+        let mut records: Records = match filter {
+            Some(filter) => Box::new(records.filter_map(move |record| {
+                let record = match record {
+                    Ok(record) => record,
+                    Err(e) => return Some(Err(e)),
+                };
+                match filter.matches(&record) {
+                    Ok(true) => Some(Ok(record)),
+                    Ok(false) => None,
+                    Err(e) => Some(Err(e)),
+                }
+            })),
+            None => records,
+        };
+
+        if !sort.is_empty() {
+            let mut buffered = records.collect::<Result<Vec<_>>>()?;
+            buffered.sort_by(|a, b| compare_records(a, b, &sort));
+            records = Box::new(buffered.into_iter().map(Ok));
+        }
+
+        records = Box::new(records.skip(offset));
+        if let Some(limit) = limit {
+            records = Box::new(records.take(limit));
+        }
+
+        if !select.is_empty() {
+            records = Box::new(records.map(move |record| Ok(project(&record?, &select))));
+        }
+        Ok(records)
+    }
+}
+
+/// `column=expression`
+fn parse_transform(s: &str, append: bool) -> Result<Transform> {
+    let (column, expr) = s
+        .split_once('=')
+        .ok_or_else(|| anyhow::format_err!("Expected column=expression: {}", s))?;
+    let column = column.trim().trim_matches('`').to_string();
+    if column.is_empty() {
+        return Err(anyhow::format_err!("Expected column=expression: {}", s));
+    }
+    Ok(Transform {
+        column,
+        expr: expr.parse()?,
+        append,
+    })
+}
+
+/// `column` or `alias=column`
+fn parse_selection(s: &str) -> (String, String) {
+    match s.split_once('=') {
+        Some((alias, column)) => (column.trim().to_string(), alias.trim().to_string()),
+        None => (s.trim().to_string(), s.trim().to_string()),
+    }
+}
+
+/// `column[:asc|desc][:num|str]`, e.g. `Kit Number:desc:num`
+fn parse_sort_key(s: &str) -> Result<SortKey> {
+    let mut parts = s.split(':');
+    let column = parts.next().unwrap_or_default().trim().to_string();
+    if column.is_empty() {
+        return Err(anyhow::format_err!("Invalid sort key: {}", s));
+    }
+    let mut key = SortKey {
+        column,
+        descending: false,
+        ordering: SortOrdering::Auto,
+    };
+    for modifier in parts {
+        match modifier.trim().to_lowercase().as_str() {
+            "asc" => key.descending = false,
+            "desc" => key.descending = true,
+            "num" | "numeric" => key.ordering = SortOrdering::Numeric,
+            "str" | "lexical" => key.ordering = SortOrdering::Lexical,
+            _ => return Err(anyhow::format_err!("Invalid sort modifier: {}", modifier)),
+        }
+    }
+    Ok(key)
+}
+
+fn compare_records(a: &Record, b: &Record, keys: &[SortKey]) -> Ordering {
+    for key in keys {
+        let lhs = a.get(&key.column).unwrap_or(&Value::Null);
+        let rhs = b.get(&key.column).unwrap_or(&Value::Null);
+        // nulls sort last regardless of direction
+        let ord = match (lhs.is_null(), rhs.is_null()) {
+            (true, true) => Ordering::Equal,
+            (true, false) => return Ordering::Greater,
+            (false, true) => return Ordering::Less,
+            _ => compare_values(lhs, rhs, key.ordering),
+        };
+        let ord = if key.descending { ord.reverse() } else { ord };
+        if ord != Ordering::Equal {
+            return ord;
+        }
+    }
+    Ordering::Equal
+}
+
+fn compare_values(lhs: &Value, rhs: &Value, ordering: SortOrdering) -> Ordering {
+    match ordering {
+        SortOrdering::Lexical => expr::as_text(lhs).cmp(&expr::as_text(rhs)),
+        // ordering by kind first keeps the comparison transitive on mixed columns
+        SortOrdering::Auto | SortOrdering::Numeric => {
+            match (expr::as_number(lhs), expr::as_number(rhs)) {
+                (Some(a), Some(b)) => a.total_cmp(&b),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => expr::as_text(lhs).cmp(&expr::as_text(rhs)),
+            }
+        }
+    }
+}
+
+fn project(record: &Record, select: &[(String, String)]) -> Record {
+    select
+        .iter()
+        .map(|(column, alias)| {
+            let value = record.get(column).cloned().unwrap_or(Value::Null);
+            (alias.clone(), value)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn records(values: Vec<Value>) -> Records {
+        let records = values
+            .into_iter()
+            .map(|v| Ok(v.as_object().unwrap().clone()))
+            .collect::<Vec<_>>();
+        Box::new(records.into_iter())
+    }
+
+    #[test]
+    fn test_parse_sort_key() -> Result<()> {
+        let key = parse_sort_key("Kit Number:desc:num")?;
+        assert_eq!(key.column, "Kit Number");
+        assert!(key.descending);
+        assert_eq!(key.ordering, SortOrdering::Numeric);
+        assert!(parse_sort_key("a:sideways").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_apply() -> Result<()> {
+        let input = records(vec![
+            json!({"name": "a", "pos": "GK", "kit": "10"}),
+            json!({"name": "b", "pos": "DF", "kit": "2"}),
+            json!({"name": "c", "pos": "GK", "kit": "9"}),
+            json!({"name": "d", "pos": "GK", "kit": "1"}),
+        ]);
+        let query = Query {
+            filter: Some(r#"pos == "GK""#.parse()?),
+            sort: vec![parse_sort_key("kit")?],
+            offset: 1,
+            limit: Some(1),
+            select: vec![parse_selection("player=name"), parse_selection("kit")],
+            ..Default::default()
+        };
+        let output = query.apply(input)?.collect::<Result<Vec<_>>>()?;
+        assert_eq!(output.len(), 1);
+        assert_eq!(
+            Value::Object(output[0].clone()),
+            json!({"player": "c", "kit": "9"})
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_sort_lexical_and_nulls_last() -> Result<()> {
+        let input = records(vec![
+            json!({"kit": "10"}),
+            json!({"kit": null}),
+            json!({"kit": "9"}),
+        ]);
+        let query = Query {
+            sort: vec![parse_sort_key("kit:str:desc")?],
+            ..Default::default()
+        };
+        let output = query.apply(input)?.collect::<Result<Vec<_>>>()?;
+        let kits = output.iter().map(|r| r["kit"].clone()).collect::<Vec<_>>();
+        assert_eq!(kits, vec![json!("9"), json!("10"), Value::Null]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sort_auto_mixed() -> Result<()> {
+        let input = records(vec![
+            json!({"v": "10"}),
+            json!({"v": "1a"}),
+            json!({"v": "9"}),
+            json!({"v": "b"}),
+            json!({"v": 2}),
+        ]);
+        let query = Query {
+            sort: vec![parse_sort_key("v")?],
+            ..Default::default()
+        };
+        let output = query.apply(input)?.collect::<Result<Vec<_>>>()?;
+        let values = output.iter().map(|r| r["v"].clone()).collect::<Vec<_>>();
+        assert_eq!(
+            values,
+            vec![json!(2), json!("9"), json!("10"), json!("1a"), json!("b")]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_transforms() -> Result<()> {
+        let input = records(vec![
+            json!({"name": "a", "kg": "80", "m": "2"}),
+            json!({"name": "b", "kg": "90", "m": "1.5"}),
+        ]);
+        let query = Query {
+            transforms: vec![
+                parse_transform("name=upper(name)", false)?,
+                parse_transform("bmi=round(kg / (m * m), 1)", true)?,
+            ],
+            filter: Some("bmi > 30".parse()?),
+            ..Default::default()
+        };
+        let output = query.apply(input)?.collect::<Result<Vec<_>>>()?;
+        assert_eq!(
+            Value::Object(output[0].clone()),
+            json!({"name": "B", "kg": "90", "m": "1.5", "bmi": 40.0})
+        );
+
+        let missing = Query {
+            transforms: vec![parse_transform("x=1", false)?],
+            ..Default::default()
+        };
+        let input = records(vec![json!({"a": 1})]);
+        assert!(missing.apply(input)?.next().unwrap().is_err());
+        assert!(parse_transform("upper(name)", false).is_err());
+        Ok(())
+    }
+}
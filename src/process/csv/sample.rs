@@ -0,0 +1,145 @@
+use anyhow::Result;
+use indexmap::IndexMap;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+use super::{expr, read_text_records, source::Record, writer};
+use crate::{
+    cli::csv_options::{CsvSampleOptions, CsvShuffleOptions},
+    utils::get_writer,
+};
+
+pub fn process_csv_sample(options: &CsvSampleOptions) -> Result<()> {
+    let (headers, records) = read_text_records(&options.input, options.delimiter)?;
+    if let Some(by) = &options.by {
+        if !headers.contains(by) {
+            return Err(anyhow::format_err!("Unknown column: {}", by));
+        }
+    }
+    let mut rng = new_rng(options.seed);
+    let sample = sample(records, options.n, options.by.as_deref(), &mut rng)?;
+
+    let mut writer = writer::new_writer(
+        options.format,
+        get_writer(&options.output)?,
+        Default::default(),
+    );
+    for record in &sample {
+        writer.write_record(record)?;
+    }
+    writer.finish()
+}
+
+/// the whole file is buffered, the order only depends on the seed and the row count
+pub fn process_csv_shuffle(options: &CsvShuffleOptions) -> Result<()> {
+    let (_, records) = read_text_records(&options.input, options.delimiter)?;
+    let mut records = records.collect::<Result<Vec<_>>>()?;
+    records.shuffle(&mut new_rng(options.seed));
+
+    let mut writer = writer::new_writer(
+        options.format,
+        get_writer(&options.output)?,
+        Default::default(),
+    );
+    for record in &records {
+        writer.write_record(record)?;
+    }
+    writer.finish()
+}
+
+fn new_rng(seed: Option<u64>) -> StdRng {
+    match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    }
+}
+
+/// algorithm R, keeps `n` uniformly chosen rows in memory while streaming the rest
+struct Reservoir {
+    seen: usize,
+    /// `(row number, record)`
+    rows: Vec<(usize, Record)>,
+}
+
+impl Reservoir {
+    fn new() -> Self {
+        Self {
+            seen: 0,
+            rows: Vec::new(),
+        }
+    }
+
+    fn offer(&mut self, n: usize, index: usize, record: Record, rng: &mut impl Rng) {
+        if self.rows.len() < n {
+            self.rows.push((index, record));
+        } else {
+            let slot = rng.gen_range(0..=self.seen);
+            if slot < n {
+                self.rows[slot] = (index, record);
+            }
+        }
+        self.seen += 1;
+    }
+}
+
+/// `n` rows overall, or `n` rows per distinct value of the `by` column, in file order
+fn sample(
+    records: impl Iterator<Item = Result<Record>>,
+    n: usize,
+    by: Option<&str>,
+    rng: &mut impl Rng,
+) -> Result<Vec<Record>> {
+    let mut strata: IndexMap<String, Reservoir> = IndexMap::new();
+    for (index, record) in records.enumerate() {
+        let record = record?;
+        let key = by
+            .and_then(|column| record.get(column))
+            .map(expr::as_text)
+            .unwrap_or_default();
+        strata
+            .entry(key)
+            .or_insert_with(Reservoir::new)
+            .offer(n, index, record, rng);
+    }
+    let mut rows = strata
+        .into_values()
+        .flat_map(|reservoir| reservoir.rows)
+        .collect::<Vec<_>>();
+    rows.sort_by_key(|(index, _)| *index);
+    Ok(rows.into_iter().map(|(_, record)| record).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn records() -> impl Iterator<Item = Result<Record>> {
+        (0..100).map(|i| {
+            let group = if i % 10 == 0 { "rare" } else { "common" };
+            Ok(json!({"id": i, "group": group})
+                .as_object()
+                .unwrap()
+                .clone())
+        })
+    }
+
+    #[test]
+    fn test_sample() -> Result<()> {
+        let ids = |rows: Vec<Record>| rows.iter().map(|r| r["id"].clone()).collect::<Vec<_>>();
+        let first = ids(sample(records(), 5, None, &mut new_rng(Some(42)))?);
+        let second = ids(sample(records(), 5, None, &mut new_rng(Some(42)))?);
+        assert_eq!(first.len(), 5);
+        assert_eq!(first, second);
+        let mut sorted = first.clone();
+        sorted.sort_by_key(|v| v.as_i64());
+        assert_eq!(first, sorted);
+
+        let all = sample(records(), 500, None, &mut new_rng(Some(1)))?;
+        assert_eq!(all.len(), 100);
+
+        let stratified = sample(records(), 3, Some("group"), &mut new_rng(Some(7)))?;
+        let rare = stratified.iter().filter(|r| r["group"] == "rare").count();
+        assert_eq!((stratified.len(), rare), (6, 3));
+        Ok(())
+    }
+}
@@ -0,0 +1,287 @@
+use anyhow::Result;
+use serde_json::{json, Map, Value};
+use std::{collections::HashSet, io::Write, path::Path};
+
+use super::{build_reader, infer::TypeGuess, read_headers};
+use crate::{
+    cli::csv_options::{ColumnType, CsvSchemaOptions, SchemaEmit},
+    utils::get_writer,
+};
+
+/// strict, reserved and edition keywords, none of them can be a plain field name
+const RUST_KEYWORDS: [&str; 52] = [
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "crate",
+    "do", "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "gen", "if", "impl",
+    "in", "let", "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref",
+    "return", "self", "Self", "static", "struct", "super", "trait", "true", "try", "type",
+    "typeof", "unsafe", "unsized", "use", "virtual", "where", "while", "yield",
+];
+
+/// a column as the generated code sees it
+#[derive(Debug, Clone, PartialEq)]
+struct Field {
+    name: String,
+    ty: ColumnType,
+    /// empty cells were sampled, string columns keep them as empty strings and never are
+    nullable: bool,
+}
+
+pub fn process_csv_schema(options: &CsvSchemaOptions) -> Result<()> {
+    let mut reader = build_reader(&options.input, options.delimiter, options.header)?;
+    let headers = read_headers(&mut reader, options.header)?;
+    let mut guesses = vec![TypeGuess::default(); headers.len()];
+    for record in reader.into_records().take(options.sample_size) {
+        for (guess, value) in guesses.iter_mut().zip(record?.iter()) {
+            guess.observe(value);
+        }
+    }
+    let fields = headers
+        .into_iter()
+        .zip(&guesses)
+        .map(|(name, guess)| {
+            let ty = guess.column_type();
+            Field {
+                name,
+                ty,
+                nullable: guess.nullable() && ty != ColumnType::String,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let name = match &options.name {
+        Some(name) if options.emit != SchemaEmit::JsonSchema => {
+            check_type_name(name)?;
+            name.clone()
+        }
+        Some(name) => name.clone(),
+        None => type_name(&options.input),
+    };
+    let text = match options.emit {
+        SchemaEmit::JsonSchema => {
+            serde_json::to_string_pretty(&json_schema(&name, &fields))? + "\n"
+        }
+        SchemaEmit::Rust => rust_struct(&name, &fields),
+        SchemaEmit::TypeScript => typescript_interface(&name, &fields),
+    };
+    let mut output = get_writer(&options.output)?;
+    output.write_all(text.as_bytes())?;
+    output.flush()?;
+    Ok(())
+}
+
+/// `players_2024.csv` becomes `Players2024`, stdin becomes `Record`
+fn type_name(input: &str) -> String {
+    let stem = Path::new(input)
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = stem
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut chars = word.chars();
+            let first = chars.next().unwrap_or_default().to_ascii_uppercase();
+            std::iter::once(first).chain(chars).collect::<String>()
+        })
+        .collect::<String>();
+    match name.chars().next() {
+        // `self.csv` would otherwise become `Self`
+        Some(_) if RUST_KEYWORDS.contains(&name.as_str()) => format!("{}Record", name),
+        Some(c) if c.is_ascii_alphabetic() => name,
+        Some(_) => format!("Record{}", name),
+        None => "Record".to_string(),
+    }
+}
+
+/// a `--name` has to be usable as is, keywords are matched case-sensitively so `Type` is fine
+fn check_type_name(name: &str) -> Result<()> {
+    let valid = name
+        .chars()
+        .enumerate()
+        .all(|(i, c)| c == '_' || c.is_ascii_alphabetic() || (i > 0 && c.is_ascii_digit()));
+    if !valid || name.is_empty() || name == "_" || RUST_KEYWORDS.contains(&name) {
+        return Err(anyhow::format_err!("Invalid type name: {}", name));
+    }
+    Ok(())
+}
+
+fn json_schema(name: &str, fields: &[Field]) -> Value {
+    let properties = fields
+        .iter()
+        .map(|field| {
+            let ty = match field.ty {
+                ColumnType::String => "string",
+                ColumnType::Integer => "integer",
+                ColumnType::Float => "number",
+                ColumnType::Boolean => "boolean",
+            };
+            let ty = if field.nullable {
+                json!([ty, "null"])
+            } else {
+                json!(ty)
+            };
+            (field.name.clone(), json!({ "type": ty }))
+        })
+        .collect::<Map<_, _>>();
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": name,
+        "type": "object",
+        "properties": properties,
+        "required": fields.iter().map(|f| f.name.as_str()).collect::<Vec<_>>(),
+    })
+}
+
+fn rust_struct(name: &str, fields: &[Field]) -> String {
+    let mut ret = String::from("use serde::{Deserialize, Serialize};\n\n");
+    ret.push_str("#[derive(Debug, Clone, Serialize, Deserialize)]\n");
+    ret.push_str(&format!("pub struct {} {{\n", name));
+    let mut used = HashSet::new();
+    for field in fields {
+        let ident = rust_ident(&field.name, &mut used);
+        if ident.trim_start_matches("r#") != field.name {
+            ret.push_str(&format!("    #[serde(rename = {:?})]\n", field.name));
+        }
+        let ty = match field.ty {
+            ColumnType::String => "String",
+            ColumnType::Integer => "i64",
+            ColumnType::Float => "f64",
+            ColumnType::Boolean => "bool",
+        };
+        let ty = if field.nullable {
+            format!("Option<{}>", ty)
+        } else {
+            ty.to_string()
+        };
+        ret.push_str(&format!("    pub {}: {},\n", ident, ty));
+    }
+    ret.push_str("}\n");
+    ret
+}
+
+/// a unique snake_case identifier, `Kit Number` becomes `kit_number` and `type` becomes `r#type`
+fn rust_ident(name: &str, used: &mut HashSet<String>) -> String {
+    let mut ident = String::new();
+    let mut prev: Option<char> = None;
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            // split camelCase words
+            if c.is_ascii_uppercase() && prev.is_some_and(|p| p.is_ascii_lowercase()) {
+                ident.push('_');
+            }
+            ident.push(c.to_ascii_lowercase());
+        } else if !ident.is_empty() && !ident.ends_with('_') {
+            ident.push('_');
+        }
+        prev = Some(c);
+    }
+    let ident = ident.trim_end_matches('_');
+    let mut ident = match ident.chars().next() {
+        Some(c) if c.is_ascii_alphabetic() => ident.to_string(),
+        Some(_) => format!("field_{}", ident),
+        None => "field".to_string(),
+    };
+    if RUST_KEYWORDS.contains(&ident.as_str()) {
+        ident = match ident.as_str() {
+            // not allowed as raw identifiers
+            "crate" | "self" | "super" => format!("{}_", ident),
+            _ => format!("r#{}", ident),
+        };
+    }
+    let mut unique = ident.clone();
+    let mut n = 2;
+    while !used.insert(unique.clone()) {
+        unique = format!("{}_{}", ident, n);
+        n += 1;
+    }
+    unique
+}
+
+fn typescript_interface(name: &str, fields: &[Field]) -> String {
+    let mut ret = format!("export interface {} {{\n", name);
+    for field in fields {
+        let valid = field.name.chars().enumerate().all(|(i, c)| {
+            c == '_' || c == '$' || c.is_ascii_alphabetic() || (i > 0 && c.is_ascii_digit())
+        });
+        let key = if valid && !field.name.is_empty() {
+            field.name.clone()
+        } else {
+            serde_json::to_string(&field.name).unwrap_or_default()
+        };
+        let ty = match field.ty {
+            ColumnType::String => "string",
+            ColumnType::Integer | ColumnType::Float => "number",
+            ColumnType::Boolean => "boolean",
+        };
+        let null = if field.nullable { " | null" } else { "" };
+        ret.push_str(&format!("  {}: {}{};\n", key, ty, null));
+    }
+    ret.push_str("}\n");
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields() -> Vec<Field> {
+        let field = |name: &str, ty, nullable| Field {
+            name: name.to_string(),
+            ty,
+            nullable,
+        };
+        vec![
+            field("Name", ColumnType::String, false),
+            field("Kit Number", ColumnType::Integer, true),
+            field("type", ColumnType::Float, false),
+            field("isActive", ColumnType::Boolean, false),
+        ]
+    }
+
+    #[test]
+    fn test_emit() {
+        assert_eq!(type_name("assets/juventus_2024.csv"), "Juventus2024");
+        assert_eq!(type_name("-"), "Record");
+        assert_eq!(type_name("self.csv"), "SelfRecord");
+        assert_eq!(type_name("type.csv"), "Type");
+        assert!(check_type_name("Player2").is_ok());
+        assert!(check_type_name("Type").is_ok());
+        for name in ["Self", "struct", "2nd", "Kit Number", "_", ""] {
+            assert!(check_type_name(name).is_err(), "{}", name);
+        }
+
+        let schema = json_schema("Player", &fields());
+        assert_eq!(
+            schema["properties"]["Kit Number"]["type"],
+            json!(["integer", "null"])
+        );
+        assert_eq!(schema["required"][3], "isActive");
+
+        let rust = rust_struct("Player", &fields());
+        assert!(rust.contains("    #[serde(rename = \"Name\")]\n    pub name: String,\n"));
+        assert!(rust.contains("    pub kit_number: Option<i64>,\n"));
+        assert!(rust.contains("    pub r#type: f64,\n"));
+        assert!(rust.contains("    #[serde(rename = \"isActive\")]\n    pub is_active: bool,\n"));
+
+        let ts = typescript_interface("Player", &fields());
+        assert!(ts.contains("  \"Kit Number\": number | null;\n"));
+        assert!(ts.contains("  isActive: boolean;\n"));
+
+        let mut used = HashSet::new();
+        assert_eq!(rust_ident("a b", &mut used), "a_b");
+        assert_eq!(rust_ident("a-b", &mut used), "a_b_2");
+        assert_eq!(rust_ident("2nd", &mut used), "field_2nd");
+        for keyword in ["try", "gen", "yield", "abstract"] {
+            assert_eq!(rust_ident(keyword, &mut used), format!("r#{}", keyword));
+        }
+        let rust = rust_struct(
+            "Record",
+            &[Field {
+                name: "try".to_string(),
+                ty: ColumnType::String,
+                nullable: false,
+            }],
+        );
+        assert!(rust.contains("{\n    pub r#try: String,\n}"));
+    }
+}
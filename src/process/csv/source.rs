@@ -0,0 +1,67 @@
+use anyhow::Result;
+use serde_json::{Map, Value};
+use std::io::Read;
+
+use crate::cli::csv_options::InputFormat;
+
+pub type Record = Map<String, Value>;
+pub type Records = Box<dyn Iterator<Item = Result<Record>>>;
+
+/// read JSON, YAML or NDJSON records, documents must be an array of objects or a single object
+pub fn read_documents(format: InputFormat, reader: Box<dyn Read>) -> Result<Records> {
+    let records: Records = match format {
+        InputFormat::Json => {
+            let value: Value = serde_json::from_reader(reader)?;
+            Box::new(document_records(value)?.into_iter().map(Ok))
+        }
+        InputFormat::Yaml => {
+            let value: Value = serde_yaml::from_reader(reader)?;
+            Box::new(document_records(value)?.into_iter().map(Ok))
+        }
+        InputFormat::Ndjson => {
+            let values = serde_json::Deserializer::from_reader(reader).into_iter::<Value>();
+            Box::new(values.map(|v| into_record(v?)))
+        }
+        InputFormat::Csv => unreachable!("CSV input is read by the csv reader"),
+        InputFormat::Excel => unreachable!("workbooks are read by calamine"),
+    };
+    Ok(records)
+}
+
+fn document_records(value: Value) -> Result<Vec<Record>> {
+    match value {
+        Value::Array(items) => items.into_iter().map(into_record).collect(),
+        Value::Null => Ok(Vec::new()),
+        value => Ok(vec![into_record(value)?]),
+    }
+}
+
+fn into_record(value: Value) -> Result<Record> {
+    match value {
+        Value::Object(map) => Ok(map),
+        value => Err(anyhow::format_err!("Expected an object, found: {}", value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_documents() -> Result<()> {
+        let json = r#"[{"a": 1}, {"a": 2}]"#;
+        let records = read_documents(InputFormat::Json, Box::new(json.as_bytes()))?;
+        assert_eq!(records.count(), 2);
+
+        let yaml = "- a: 1\n- a: 2\n  b: x\n";
+        let records = read_documents(InputFormat::Yaml, Box::new(yaml.as_bytes()))?
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(records[1]["b"], "x");
+
+        let ndjson = "{\"a\": 1}\n{\"a\": 2}\n[1]\n";
+        let records = read_documents(InputFormat::Ndjson, Box::new(ndjson.as_bytes()))?
+            .collect::<Result<Vec<_>>>();
+        assert!(records.is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,310 @@
+use anyhow::Result;
+use csv::{StringRecord, Writer, WriterBuilder};
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use super::{build_reader, read_headers};
+use crate::cli::csv_options::CsvSplitOptions;
+
+/// column mode keeps at most this many files open, the least recently used one is closed
+/// and reopened in append mode when its value shows up again
+const MAX_OPEN_FILES: usize = 256;
+
+/// how rows are assigned to output files
+enum Strategy {
+    Rows(usize),
+    Size(u64),
+    Column(usize),
+}
+
+/// rows are streamed straight into the chunk files, returns the paths written
+pub fn process_csv_split(options: &CsvSplitOptions) -> Result<Vec<PathBuf>> {
+    let mut reader = build_reader(&options.input, options.delimiter, true)?;
+    let headers = StringRecord::from(read_headers(&mut reader, true)?);
+    let strategy = match (options.rows, options.size, &options.column) {
+        (Some(0), _, _) => return Err(anyhow::format_err!("--rows must be greater than zero")),
+        (Some(rows), _, _) => Strategy::Rows(rows),
+        (_, Some(size), _) => Strategy::Size(size),
+        (_, _, Some(column)) => {
+            let index = headers
+                .iter()
+                .position(|h| h == column)
+                .ok_or_else(|| anyhow::format_err!("Unknown column: {}", column))?;
+            Strategy::Column(index)
+        }
+        _ => {
+            return Err(anyhow::format_err!(
+                "One of --rows, --size or --column is required"
+            ))
+        }
+    };
+
+    fs::create_dir_all(&options.output_dir)?;
+    let prefix = match &options.prefix {
+        Some(prefix) => prefix.clone(),
+        None if options.input == "-" => "split".to_string(),
+        None => Path::new(&options.input)
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "split".to_string()),
+    };
+    let mut splitter = Splitter {
+        dir: &options.output_dir,
+        prefix,
+        headers,
+        delimiter: options.delimiter as u8,
+        paths: Vec::new(),
+    };
+
+    match strategy {
+        Strategy::Rows(_) | Strategy::Size(_) => {
+            let mut current: Option<Writer<File>> = None;
+            let (mut rows, mut bytes) = (0usize, 0u64);
+            for record in reader.into_records() {
+                let record = record?;
+                let size = record_size(&record, splitter.delimiter);
+                // a chunk always takes at least one row, even if that row alone is over the limit
+                let full = match strategy {
+                    Strategy::Rows(max) => rows >= max,
+                    Strategy::Size(max) => rows > 0 && bytes + size > max,
+                    Strategy::Column(_) => unreachable!(),
+                };
+                if current.is_none() || full {
+                    if let Some(mut writer) = current.take() {
+                        writer.flush()?;
+                    }
+                    let name = format!("{:04}", splitter.paths.len() + 1);
+                    current = Some(splitter.create(&name)?);
+                    rows = 0;
+                    bytes = record_size(&splitter.headers, splitter.delimiter);
+                }
+                if let Some(writer) = current.as_mut() {
+                    writer.write_record(&record)?;
+                }
+                rows += 1;
+                bytes += size;
+            }
+            if let Some(mut writer) = current {
+                writer.flush()?;
+            }
+        }
+        Strategy::Column(index) => {
+            split_by_column(&mut splitter, reader.into_records(), index, MAX_OPEN_FILES)?
+        }
+    }
+    Ok(splitter.paths)
+}
+
+/// one file per distinct value, with no more than `max_open` of them open at a time
+fn split_by_column<R: Read>(
+    splitter: &mut Splitter,
+    records: csv::StringRecordsIntoIter<R>,
+    index: usize,
+    max_open: usize,
+) -> Result<()> {
+    // value to its index in `splitter.paths`
+    let mut created: HashMap<String, usize> = HashMap::new();
+    // open writers with the row they were last used for
+    let mut open: HashMap<String, (Writer<File>, usize)> = HashMap::new();
+    for (row, record) in records.enumerate() {
+        let record = record?;
+        let value = record.get(index).unwrap_or_default();
+        if !open.contains_key(value) {
+            if open.len() >= max_open.max(1) {
+                let oldest = open
+                    .iter()
+                    .min_by_key(|(_, (_, used))| *used)
+                    .map(|(value, _)| value.clone());
+                if let Some((mut writer, _)) = oldest.and_then(|value| open.remove(&value)) {
+                    writer.flush()?;
+                }
+            }
+            let writer = match created.get(value) {
+                Some(path) => splitter.reopen(*path)?,
+                None => {
+                    let writer = splitter.create(&sanitize(value))?;
+                    created.insert(value.to_string(), splitter.paths.len() - 1);
+                    writer
+                }
+            };
+            open.insert(value.to_string(), (writer, row));
+        }
+        if let Some((writer, used)) = open.get_mut(value) {
+            writer.write_record(&record)?;
+            *used = row;
+        }
+    }
+    for (writer, _) in open.values_mut() {
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+struct Splitter<'a> {
+    dir: &'a Path,
+    prefix: String,
+    headers: StringRecord,
+    delimiter: u8,
+    paths: Vec<PathBuf>,
+}
+
+impl Splitter<'_> {
+    /// create `<prefix>_<name>.csv` and write the header row into it,
+    /// names that collide after sanitizing get a numeric suffix
+    fn create(&mut self, name: &str) -> Result<Writer<File>> {
+        let mut path = self.dir.join(format!("{}_{}.csv", self.prefix, name));
+        let mut suffix = 1;
+        while self.paths.contains(&path) {
+            suffix += 1;
+            path = self
+                .dir
+                .join(format!("{}_{}_{}.csv", self.prefix, name, suffix));
+        }
+        let mut writer = WriterBuilder::new()
+            .delimiter(self.delimiter)
+            .from_path(&path)?;
+        writer.write_record(&self.headers)?;
+        self.paths.push(path);
+        Ok(writer)
+    }
+
+    /// append to the file at `index` in `paths`, its header is already written
+    fn reopen(&self, index: usize) -> Result<Writer<File>> {
+        let file = OpenOptions::new().append(true).open(&self.paths[index])?;
+        Ok(WriterBuilder::new()
+            .delimiter(self.delimiter)
+            .from_writer(file))
+    }
+}
+
+/// encoded size of a row: fields, quoting where needed, delimiters and the newline
+fn record_size(record: &StringRecord, delimiter: u8) -> u64 {
+    let quoting = record
+        .iter()
+        .filter(|field| {
+            field
+                .bytes()
+                .any(|b| b == delimiter || b == b'"' || b == b'\n' || b == b'\r')
+        })
+        .map(|field| 2 + field.matches('"').count())
+        .sum::<usize>();
+    (record.as_slice().len() + quoting + record.len().max(1)) as u64
+}
+
+/// make a column value safe to use in a file name
+fn sanitize(value: &str) -> String {
+    if value.trim().is_empty() {
+        return "empty".to_string();
+    }
+    value
+        .trim()
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(dir: &Path) -> CsvSplitOptions {
+        CsvSplitOptions {
+            input: "assets/juventus.csv".into(),
+            rows: None,
+            size: None,
+            column: None,
+            output_dir: dir.to_path_buf(),
+            prefix: None,
+            delimiter: ',',
+        }
+    }
+
+    fn count_rows(paths: &[PathBuf]) -> Result<usize> {
+        let mut total = 0;
+        for path in paths {
+            let mut reader = csv::Reader::from_path(path)?;
+            assert_eq!(reader.headers()?.get(0), Some("Name"));
+            for record in reader.records() {
+                assert_ne!(record?.get(0), Some("Name"));
+                total += 1;
+            }
+        }
+        Ok(total)
+    }
+
+    #[test]
+    fn test_split() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("rcli-split-{}", std::process::id()));
+
+        let by_rows = CsvSplitOptions {
+            rows: Some(10),
+            ..options(&dir)
+        };
+        let paths = process_csv_split(&by_rows)?;
+        assert_eq!(paths.len(), 3);
+        assert!(paths[0].ends_with("juventus_0001.csv"));
+        assert_eq!(count_rows(&paths)?, 27);
+
+        let by_column = CsvSplitOptions {
+            column: Some("Position".into()),
+            prefix: Some("pos".into()),
+            ..options(&dir)
+        };
+        let paths = process_csv_split(&by_column)?;
+        assert_eq!(paths.len(), 10);
+        assert!(paths[0].ends_with("pos_Goalkeeper.csv"));
+        assert_eq!(count_rows(&paths)?, 27);
+
+        let by_size = CsvSplitOptions {
+            size: Some(300),
+            prefix: Some("size".into()),
+            ..options(&dir)
+        };
+        let paths = process_csv_split(&by_size)?;
+        assert!(paths.len() > 3);
+        for path in &paths {
+            assert!(fs::metadata(path)?.len() <= 300);
+        }
+        assert_eq!(count_rows(&paths)?, 27);
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_by_column_reopens_files() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("rcli-split-lru-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+        let mut reader = build_reader("assets/juventus.csv", ',', true)?;
+        let headers = StringRecord::from(read_headers(&mut reader, true)?);
+        let mut splitter = Splitter {
+            dir: &dir,
+            prefix: "pos".into(),
+            headers,
+            delimiter: b',',
+            paths: Vec::new(),
+        };
+        split_by_column(&mut splitter, reader.into_records(), 1, 2)?;
+        assert_eq!(splitter.paths.len(), 10);
+        assert_eq!(count_rows(&splitter.paths)?, 27);
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!(sanitize("Centre-Back"), "Centre-Back");
+        assert_eq!(sanitize("Left Winger/2"), "Left_Winger_2");
+        assert_eq!(sanitize(" "), "empty");
+    }
+}
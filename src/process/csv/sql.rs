@@ -0,0 +1,169 @@
+use anyhow::Result;
+use rusqlite::{params_from_iter, types::Value as SqlValue, Connection};
+use serde_json::{Number, Value};
+use std::collections::HashSet;
+
+use super::{build_reader, infer, read_headers, source::Record, writer};
+use crate::{
+    cli::csv_options::{ColumnType, CsvQueryOptions},
+    utils::get_writer,
+};
+
+/// rows sampled per file to pick the SQLite column types
+const SAMPLE_SIZE: usize = 1000;
+
+/// load every table into an in-memory SQLite database and write the query result
+pub fn process_csv_query(options: &CsvQueryOptions) -> Result<()> {
+    let mut conn = Connection::open_in_memory()?;
+    for (name, path) in &options.tables {
+        load_table(&mut conn, name, path, options.delimiter)?;
+    }
+
+    let mut stmt = conn.prepare(&options.sql)?;
+    let columns = unique_names(stmt.column_names());
+    let mut rows = stmt.query([])?;
+
+    let mut writer = writer::new_writer(
+        options.format,
+        get_writer(&options.output)?,
+        Default::default(),
+    );
+    while let Some(row) = rows.next()? {
+        let mut record = Record::with_capacity(columns.len());
+        for (i, column) in columns.iter().enumerate() {
+            record.insert(column.clone(), to_json_value(row.get(i)?));
+        }
+        writer.write_record(&record)?;
+    }
+    writer.finish()
+}
+
+/// records can't hold two `Name` columns, so repeats become `Name_2`, `Name_3`, ...
+fn unique_names(names: Vec<&str>) -> Vec<String> {
+    let mut used = HashSet::with_capacity(names.len());
+    names
+        .into_iter()
+        .map(|name| {
+            let mut unique = name.to_string();
+            let mut n = 1;
+            while !used.insert(unique.clone()) {
+                n += 1;
+                unique = format!("{}_{}", name, n);
+            }
+            unique
+        })
+        .collect()
+}
+
+fn load_table(conn: &mut Connection, name: &str, path: &str, delimiter: char) -> Result<()> {
+    let mut reader = build_reader(path, delimiter, true)?;
+    let headers = read_headers(&mut reader, true)?;
+    let mut records = reader.into_records();
+    let sample = records
+        .by_ref()
+        .take(SAMPLE_SIZE)
+        .collect::<Result<Vec<_>, _>>()?;
+    let types = infer::infer_types(&sample, headers.len());
+
+    let columns = headers
+        .iter()
+        .zip(&types)
+        .map(|(h, ty)| format!("{} {}", quote_ident(h), affinity(*ty)))
+        .collect::<Vec<_>>();
+    let placeholders = vec!["?"; headers.len()].join(", ");
+
+    let tx = conn.transaction()?;
+    tx.execute(
+        &format!(
+            "CREATE TABLE {} ({})",
+            quote_ident(name),
+            columns.join(", ")
+        ),
+        [],
+    )?;
+    {
+        let mut insert = tx.prepare(&format!(
+            "INSERT INTO {} VALUES ({})",
+            quote_ident(name),
+            placeholders
+        ))?;
+        for record in sample.into_iter().map(Ok).chain(records) {
+            let record = record?;
+            let values = record
+                .iter()
+                .zip(&types)
+                .map(|(value, ty)| to_sql_value(value, *ty));
+            insert.execute(params_from_iter(values))?;
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// SQLite has no boolean type, so booleans are stored and come back as 0 and 1
+fn affinity(ty: ColumnType) -> &'static str {
+    match ty {
+        ColumnType::Integer | ColumnType::Boolean => "INTEGER",
+        ColumnType::Float => "REAL",
+        ColumnType::String => "TEXT",
+    }
+}
+
+fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// cells that don't fit the inferred type are stored as text
+fn to_sql_value(value: &str, ty: ColumnType) -> SqlValue {
+    match infer::parse_value(value, ty) {
+        Some(Value::Null) => SqlValue::Null,
+        Some(Value::Bool(b)) => SqlValue::Integer(b as i64),
+        Some(Value::Number(n)) => match n.as_i64() {
+            Some(i) => SqlValue::Integer(i),
+            None => SqlValue::Real(n.as_f64().unwrap_or_default()),
+        },
+        _ => SqlValue::Text(value.to_string()),
+    }
+}
+
+fn to_json_value(value: SqlValue) -> Value {
+    match value {
+        SqlValue::Null => Value::Null,
+        SqlValue::Integer(i) => Value::from(i),
+        SqlValue::Real(f) => Number::from_f64(f).map_or(Value::Null, Value::Number),
+        SqlValue::Text(s) => Value::String(s),
+        SqlValue::Blob(b) => Value::String(String::from_utf8_lossy(&b).into_owned()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_juventus() -> Result<()> {
+        let mut conn = Connection::open_in_memory()?;
+        load_table(&mut conn, "players", "assets/juventus.csv", ',')?;
+        let count: i64 = conn.query_row(
+            "SELECT count(*) FROM players WHERE Position = 'Goalkeeper'",
+            [],
+            |row| row.get(0),
+        )?;
+        assert_eq!(count, 4);
+
+        // kit numbers are inferred as integers so they compare as numbers
+        let max: i64 = conn.query_row("SELECT max(\"Kit Number\") FROM players", [], |row| {
+            row.get(0)
+        })?;
+        assert_eq!(max, 77);
+        Ok(())
+    }
+
+    #[test]
+    fn test_unique_names() {
+        assert_eq!(
+            unique_names(vec!["Name", "Name", "Name_2", "Age", "Name"]),
+            ["Name", "Name_2", "Name_2_2", "Age", "Name_3"]
+        );
+    }
+}
@@ -0,0 +1,498 @@
+use anyhow::Result;
+use serde_json::{Number, Value};
+use std::{
+    collections::{BTreeSet, HashMap},
+    hash::{DefaultHasher, Hash, Hasher},
+    rc::Rc,
+};
+
+use super::{
+    build_reader, expr,
+    infer::TypeGuess,
+    read_headers,
+    source::Record,
+    writer::{self, WriteOptions},
+};
+use crate::{
+    cli::csv_options::{ArrayMode, ColumnType, CsvStatsOptions, DistinctMode},
+    utils::get_writer,
+};
+
+/// number of values tracked per column when estimating the top values in `hll` mode
+const APPROX_TOP_CAPACITY: usize = 1000;
+
+/// stream the CSV once and write one profile record per column
+pub fn process_csv_stats(options: &CsvStatsOptions) -> Result<()> {
+    let mut reader = build_reader(&options.input, options.delimiter, options.header)?;
+    let headers = read_headers(&mut reader, options.header)?;
+    let mut profiles = headers
+        .iter()
+        .map(|_| ColumnProfile::new(options.distinct))
+        .collect::<Vec<_>>();
+    for record in reader.records() {
+        let record = record?;
+        for (profile, value) in profiles.iter_mut().zip(record.iter()) {
+            profile.observe(value);
+        }
+    }
+
+    let write_options = WriteOptions {
+        arrays: ArrayMode::Join,
+        ..Default::default()
+    };
+    let mut writer =
+        writer::new_writer(options.format, get_writer(&options.output)?, write_options);
+    for (name, profile) in headers.iter().zip(profiles) {
+        writer.write_record(&profile.into_record(name, options.top))?;
+    }
+    writer.finish()
+}
+
+struct ColumnProfile {
+    guess: TypeGuess,
+    count: u64,
+    empty: u64,
+    /// dropped as soon as the column turns out not to be numeric
+    numbers: Option<Numbers>,
+    values: ValueCounter,
+}
+
+impl ColumnProfile {
+    fn new(mode: DistinctMode) -> Self {
+        let (values, median) = match mode {
+            DistinctMode::Exact => (
+                ValueCounter::Exact(HashMap::new()),
+                Median::Exact(Vec::new()),
+            ),
+            DistinctMode::Hll => (
+                ValueCounter::Approx {
+                    hll: HyperLogLog::new(14),
+                    top: SpaceSaving::new(APPROX_TOP_CAPACITY),
+                },
+                Median::Approx(P2Median::new()),
+            ),
+        };
+        Self {
+            guess: TypeGuess::default(),
+            count: 0,
+            empty: 0,
+            numbers: Some(Numbers::new(median)),
+            values,
+        }
+    }
+
+    fn observe(&mut self, value: &str) {
+        self.count += 1;
+        if value.trim().is_empty() {
+            self.empty += 1;
+            return;
+        }
+        self.guess.observe(value);
+        if let Some(numbers) = &mut self.numbers {
+            match expr::as_number(&Value::String(value.to_string())) {
+                Some(n) => numbers.push(n),
+                None => self.numbers = None,
+            }
+        }
+        self.values.observe(value);
+    }
+
+    fn into_record(self, name: &str, top: usize) -> Record {
+        let ty = self.guess.column_type();
+        let mut record = Record::new();
+        record.insert("column".into(), name.into());
+        record.insert("type".into(), ty.to_string().into());
+        record.insert("count".into(), self.count.into());
+        record.insert("empty".into(), self.empty.into());
+        record.insert("distinct".into(), self.values.distinct().into());
+
+        let numeric = matches!(ty, ColumnType::Integer | ColumnType::Float);
+        let summary = match self.numbers {
+            Some(numbers) if numeric && numbers.len > 0 => {
+                let bound = |n: f64| match ty {
+                    ColumnType::Integer => Value::from(n as i64),
+                    _ => number(n),
+                };
+                [
+                    bound(numbers.min),
+                    bound(numbers.max),
+                    number(numbers.sum / numbers.len as f64),
+                    numbers.median.estimate().map_or(Value::Null, number),
+                ]
+            }
+            _ => Default::default(),
+        };
+        for (key, value) in ["min", "max", "mean", "median"].into_iter().zip(summary) {
+            record.insert(key.into(), value);
+        }
+
+        let top = self
+            .values
+            .top(top)
+            .into_iter()
+            .map(|(value, count)| Value::String(format!("{} ({})", value, count)))
+            .collect();
+        record.insert("top".into(), Value::Array(top));
+        record
+    }
+}
+
+fn number(n: f64) -> Value {
+    Number::from_f64(n).map_or(Value::Null, Value::Number)
+}
+
+/// running numeric summary, only exact mode keeps every value for the median
+struct Numbers {
+    min: f64,
+    max: f64,
+    sum: f64,
+    len: u64,
+    median: Median,
+}
+
+impl Numbers {
+    fn new(median: Median) -> Self {
+        Self {
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            sum: 0.0,
+            len: 0,
+            median,
+        }
+    }
+
+    fn push(&mut self, n: f64) {
+        self.min = self.min.min(n);
+        self.max = self.max.max(n);
+        self.sum += n;
+        self.len += 1;
+        match &mut self.median {
+            Median::Exact(values) => values.push(n),
+            Median::Approx(p2) => p2.push(n),
+        }
+    }
+}
+
+enum Median {
+    Exact(Vec<f64>),
+    Approx(P2Median),
+}
+
+impl Median {
+    fn estimate(self) -> Option<f64> {
+        match self {
+            Median::Exact(values) => exact_median(values),
+            Median::Approx(p2) => p2.estimate(),
+        }
+    }
+}
+
+fn exact_median(mut values: Vec<f64>) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(f64::total_cmp);
+    let len = values.len();
+    let median = if len.is_multiple_of(2) {
+        (values[len / 2 - 1] + values[len / 2]) / 2.0
+    } else {
+        values[len / 2]
+    };
+    Some(median)
+}
+
+/// how far each P² marker's desired position moves per value, for the 0, 0.25, 0.5, 0.75 and 1 quantiles
+const P2_INCREMENTS: [f64; 5] = [0.0, 0.25, 0.5, 0.75, 1.0];
+
+/// P² median estimate (Jain and Chlamtac), five markers instead of every value
+struct P2Median {
+    /// marker heights, the middle one estimates the median
+    heights: [f64; 5],
+    positions: [f64; 5],
+    desired: [f64; 5],
+    count: usize,
+}
+
+impl P2Median {
+    fn new() -> Self {
+        Self {
+            heights: [0.0; 5],
+            positions: [0.0, 1.0, 2.0, 3.0, 4.0],
+            desired: [0.0, 1.0, 2.0, 3.0, 4.0],
+            count: 0,
+        }
+    }
+
+    fn push(&mut self, x: f64) {
+        if self.count < 5 {
+            self.heights[self.count] = x;
+            self.count += 1;
+            if self.count == 5 {
+                self.heights.sort_by(f64::total_cmp);
+            }
+            return;
+        }
+        self.count += 1;
+
+        let h = &mut self.heights;
+        let k = if x < h[0] {
+            h[0] = x;
+            0
+        } else if x >= h[4] {
+            h[4] = x;
+            3
+        } else {
+            (1..5).find(|&i| x < h[i]).unwrap_or(4) - 1
+        };
+        for position in &mut self.positions[k + 1..] {
+            *position += 1.0;
+        }
+        for (desired, increment) in self.desired.iter_mut().zip(P2_INCREMENTS) {
+            *desired += increment;
+        }
+
+        // move the middle markers towards their desired positions
+        for i in 1..4 {
+            let n = &self.positions;
+            let d = self.desired[i] - n[i];
+            if (d >= 1.0 && n[i + 1] - n[i] > 1.0) || (d <= -1.0 && n[i - 1] - n[i] < -1.0) {
+                let d = d.signum();
+                let q = self.parabolic(i, d);
+                self.heights[i] = if self.heights[i - 1] < q && q < self.heights[i + 1] {
+                    q
+                } else {
+                    self.linear(i, d)
+                };
+                self.positions[i] += d;
+            }
+        }
+    }
+
+    fn parabolic(&self, i: usize, d: f64) -> f64 {
+        let (q, n) = (&self.heights, &self.positions);
+        q[i] + d / (n[i + 1] - n[i - 1])
+            * ((n[i] - n[i - 1] + d) * (q[i + 1] - q[i]) / (n[i + 1] - n[i])
+                + (n[i + 1] - n[i] - d) * (q[i] - q[i - 1]) / (n[i] - n[i - 1]))
+    }
+
+    fn linear(&self, i: usize, d: f64) -> f64 {
+        let (q, n) = (&self.heights, &self.positions);
+        let j = if d > 0.0 { i + 1 } else { i - 1 };
+        q[i] + d * (q[j] - q[i]) / (n[j] - n[i])
+    }
+
+    /// exact until five values are seen
+    fn estimate(&self) -> Option<f64> {
+        if self.count < 5 {
+            return exact_median(self.heights[..self.count].to_vec());
+        }
+        Some(self.heights[2])
+    }
+}
+
+enum ValueCounter {
+    Exact(HashMap<String, u64>),
+    Approx { hll: HyperLogLog, top: SpaceSaving },
+}
+
+impl ValueCounter {
+    fn observe(&mut self, value: &str) {
+        match self {
+            ValueCounter::Exact(counts) => match counts.get_mut(value) {
+                Some(count) => *count += 1,
+                None => {
+                    counts.insert(value.to_string(), 1);
+                }
+            },
+            ValueCounter::Approx { hll, top } => {
+                hll.insert(value);
+                top.insert(value);
+            }
+        }
+    }
+
+    fn distinct(&self) -> u64 {
+        match self {
+            ValueCounter::Exact(counts) => counts.len() as u64,
+            ValueCounter::Approx { hll, .. } => hll.estimate(),
+        }
+    }
+
+    /// most frequent values first, ties broken by value for stable output
+    fn top(&self, n: usize) -> Vec<(String, u64)> {
+        let mut ret = match self {
+            ValueCounter::Exact(counts) => counts
+                .iter()
+                .map(|(k, v)| (k.clone(), *v))
+                .collect::<Vec<_>>(),
+            ValueCounter::Approx { top, .. } => top
+                .counts
+                .iter()
+                .map(|(k, v)| (k.to_string(), *v))
+                .collect(),
+        };
+        ret.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        ret.truncate(n);
+        ret
+    }
+}
+
+/// HyperLogLog distinct count estimator with `2^precision` registers
+struct HyperLogLog {
+    precision: u32,
+    registers: Vec<u8>,
+}
+
+impl HyperLogLog {
+    fn new(precision: u32) -> Self {
+        Self {
+            precision,
+            registers: vec![0; 1 << precision],
+        }
+    }
+
+    fn insert(&mut self, value: &str) {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        let hash = hasher.finish();
+        let index = (hash >> (64 - self.precision)) as usize;
+        let rest = (hash << self.precision) | (1 << (self.precision - 1));
+        let rank = rest.leading_zeros() as u8 + 1;
+        self.registers[index] = self.registers[index].max(rank);
+    }
+
+    fn estimate(&self) -> u64 {
+        let m = self.registers.len() as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum = self
+            .registers
+            .iter()
+            .map(|r| 2f64.powi(-(*r as i32)))
+            .sum::<f64>();
+        let estimate = alpha * m * m / sum;
+        let zeros = self.registers.iter().filter(|r| **r == 0).count();
+        // linear counting is more accurate for small cardinalities
+        if estimate <= 2.5 * m && zeros > 0 {
+            (m * (m / zeros as f64).ln()).round() as u64
+        } else {
+            estimate.round() as u64
+        }
+    }
+}
+
+/// Space-Saving heavy hitters: keeps at most `capacity` counters, evicting the smallest
+struct SpaceSaving {
+    capacity: usize,
+    counts: HashMap<Rc<str>, u64>,
+    /// the same counters ordered by count, so the smallest is found in `O(log capacity)`
+    order: BTreeSet<(u64, Rc<str>)>,
+}
+
+impl SpaceSaving {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            counts: HashMap::new(),
+            order: BTreeSet::new(),
+        }
+    }
+
+    fn insert(&mut self, value: &str) {
+        if let Some((key, count)) = self.counts.get_key_value(value) {
+            let (key, count) = (key.clone(), *count);
+            self.order.remove(&(count, key.clone()));
+            self.order.insert((count + 1, key.clone()));
+            self.counts.insert(key, count + 1);
+            return;
+        }
+        let count = if self.counts.len() < self.capacity {
+            1
+        } else {
+            let Some((min_count, min_key)) = self.order.pop_first() else {
+                return;
+            };
+            self.counts.remove(&min_key);
+            min_count + 1
+        };
+        let key = Rc::<str>::from(value);
+        self.order.insert((count, key.clone()));
+        self.counts.insert(key, count);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_column_profile_numeric() {
+        let mut profile = ColumnProfile::new(DistinctMode::Exact);
+        for value in ["3", "1", "", "2", "10", "3"] {
+            profile.observe(value);
+        }
+        let record = profile.into_record("n", 1);
+        assert_eq!(record["type"], "integer");
+        assert_eq!(record["count"], 6);
+        assert_eq!(record["empty"], 1);
+        assert_eq!(record["distinct"], 4);
+        assert_eq!(record["min"], 1);
+        assert_eq!(record["max"], 10);
+        assert_eq!(record["mean"], 3.8);
+        assert_eq!(record["median"], 3.0);
+        assert_eq!(record["top"], serde_json::json!(["3 (2)"]));
+    }
+
+    #[test]
+    fn test_column_profile_text() {
+        let mut profile = ColumnProfile::new(DistinctMode::Hll);
+        for value in ["a", "1", "b", "a"] {
+            profile.observe(value);
+        }
+        let record = profile.into_record("s", 2);
+        assert_eq!(record["type"], "string");
+        assert_eq!(record["distinct"], 3);
+        assert_eq!(record["min"], Value::Null);
+        assert_eq!(record["top"], serde_json::json!(["a (2)", "1 (1)"]));
+    }
+
+    #[test]
+    fn test_space_saving_evicts_smallest() {
+        let mut top = SpaceSaving::new(2);
+        for value in ["a", "a", "b", "c", "a"] {
+            top.insert(value);
+        }
+        let counts = top
+            .counts
+            .iter()
+            .map(|(k, v)| (k.to_string(), *v))
+            .collect::<HashMap<_, _>>();
+        assert_eq!(counts, HashMap::from([("a".into(), 3), ("c".into(), 2)]));
+        assert_eq!(top.order.len(), 2);
+    }
+
+    #[test]
+    fn test_p2_median_estimate() {
+        let mut p2 = P2Median::new();
+        assert_eq!(p2.estimate(), None);
+        for n in [5.0, 1.0, 3.0] {
+            p2.push(n);
+        }
+        assert_eq!(p2.estimate(), Some(3.0));
+        // 0..10007 in a scrambled order
+        for i in 0..10_007u64 {
+            p2.push(((i * 7919) % 10_007) as f64);
+        }
+        let estimate = p2.estimate().unwrap();
+        assert!((estimate - 5003.0).abs() < 100.0, "{}", estimate);
+    }
+
+    #[test]
+    fn test_hyperloglog_estimate() {
+        let mut hll = HyperLogLog::new(14);
+        for i in 0..100_000 {
+            hll.insert(&i.to_string());
+        }
+        let estimate = hll.estimate() as f64;
+        assert!((estimate - 100_000.0).abs() / 100_000.0 < 0.03);
+    }
+}
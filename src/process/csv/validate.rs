@@ -0,0 +1,258 @@
+use anyhow::Result;
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::Value;
+use std::{collections::HashMap, fs};
+
+use super::{build_reader, expr, infer, read_headers, source::Record, writer};
+use crate::{
+    cli::csv_options::{ColumnType, CsvValidateOptions},
+    utils::get_writer,
+};
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Schema {
+    columns: Vec<ColumnRule>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ColumnRule {
+    name: String,
+    #[serde(default, rename = "type")]
+    ty: Option<ColumnType>,
+    #[serde(default)]
+    required: bool,
+    /// must match the whole value
+    #[serde(default)]
+    regex: Option<String>,
+    #[serde(default, rename = "enum")]
+    allowed: Option<Vec<String>>,
+    #[serde(default)]
+    min: Option<f64>,
+    #[serde(default)]
+    max: Option<f64>,
+    #[serde(default)]
+    unique: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Violation {
+    line: u64,
+    column: String,
+    message: String,
+}
+
+/// a rule bound to its column position, with the regex compiled and unique values tracked
+struct Check<'a> {
+    rule: &'a ColumnRule,
+    index: usize,
+    regex: Option<Regex>,
+    seen: HashMap<String, u64>,
+}
+
+/// write a report of every violation and return how many there were
+pub fn process_csv_validate(options: &CsvValidateOptions) -> Result<usize> {
+    let schema: Schema = serde_yaml::from_str(&fs::read_to_string(&options.schema)?)?;
+    let mut reader = build_reader(&options.input, options.delimiter, options.header)?;
+    let headers = read_headers(&mut reader, options.header)?;
+    let records = reader.into_records().map(|record| {
+        let record = record?;
+        let line = record.position().map(|p| p.line()).unwrap_or_default();
+        let values = record.iter().map(String::from).collect();
+        Ok((line, values))
+    });
+    let violations = validate(&schema, &headers, records)?;
+
+    let mut writer = writer::new_writer(
+        options.format,
+        get_writer(&options.output)?,
+        Default::default(),
+    );
+    for violation in &violations {
+        let mut record = Record::new();
+        record.insert("line".into(), violation.line.into());
+        record.insert("column".into(), violation.column.clone().into());
+        record.insert("message".into(), violation.message.clone().into());
+        writer.write_record(&record)?;
+    }
+    writer.finish()?;
+    Ok(violations.len())
+}
+
+fn validate(
+    schema: &Schema,
+    headers: &[String],
+    records: impl Iterator<Item = Result<(u64, Vec<String>)>>,
+) -> Result<Vec<Violation>> {
+    let mut violations = Vec::new();
+    let mut checks = Vec::with_capacity(schema.columns.len());
+    for rule in &schema.columns {
+        let regex = rule
+            .regex
+            .as_deref()
+            .map(|r| Regex::new(&format!("^(?:{})$", r)))
+            .transpose()?;
+        match headers.iter().position(|h| h == &rule.name) {
+            Some(index) => checks.push(Check {
+                rule,
+                index,
+                regex,
+                seen: HashMap::new(),
+            }),
+            None => violations.push(Violation {
+                line: 1,
+                column: rule.name.clone(),
+                message: "column is missing".into(),
+            }),
+        }
+    }
+
+    for record in records {
+        let (line, values) = record?;
+        for check in checks.iter_mut() {
+            let value = values.get(check.index).map_or("", String::as_str);
+            for message in check.run(value, line) {
+                violations.push(Violation {
+                    line,
+                    column: check.rule.name.clone(),
+                    message,
+                });
+            }
+        }
+    }
+    Ok(violations)
+}
+
+impl Check<'_> {
+    /// every rule the value breaks, empty optional values are not checked further
+    fn run(&mut self, value: &str, line: u64) -> Vec<String> {
+        let rule = self.rule;
+        let mut messages = Vec::new();
+        if value.trim().is_empty() {
+            if rule.required {
+                messages.push("required value is missing".into());
+            }
+            return messages;
+        }
+
+        if let Some(ty) = rule.ty {
+            if infer::parse_value(value, ty).is_none() {
+                messages.push(format!("{:?} is not a valid {}", value, ty));
+            }
+        }
+        if let Some(regex) = &self.regex {
+            if !regex.is_match(value) {
+                messages.push(format!("{:?} does not match {}", value, regex.as_str()));
+            }
+        }
+        if let Some(allowed) = &rule.allowed {
+            if !allowed.iter().any(|a| a == value) {
+                messages.push(format!("{:?} is not one of the allowed values", value));
+            }
+        }
+        if rule.min.is_some() || rule.max.is_some() {
+            match expr::as_number(&Value::String(value.to_string())) {
+                Some(n) => {
+                    if let Some(min) = rule.min.filter(|min| n < *min) {
+                        messages.push(format!("{} is less than {}", value, min));
+                    }
+                    if let Some(max) = rule.max.filter(|max| n > *max) {
+                        messages.push(format!("{} is greater than {}", value, max));
+                    }
+                }
+                None => messages.push(format!("{:?} is not a number", value)),
+            }
+        }
+        if rule.unique {
+            match self.seen.get(value) {
+                Some(first) => messages.push(format!("{:?} duplicates line {}", value, first)),
+                None => {
+                    self.seen.insert(value.to_string(), line);
+                }
+            }
+        }
+        messages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(schema: &str, headers: &[&str], rows: &[&[&str]]) -> Result<Vec<Violation>> {
+        let schema: Schema = serde_yaml::from_str(schema)?;
+        let headers = headers.iter().map(|h| h.to_string()).collect::<Vec<_>>();
+        let rows = rows
+            .iter()
+            .enumerate()
+            .map(|(i, row)| Ok((i as u64 + 2, row.iter().map(|v| v.to_string()).collect())))
+            .collect::<Vec<_>>();
+        validate(&schema, &headers, rows.into_iter())
+    }
+
+    #[test]
+    fn test_validate_rules() -> Result<()> {
+        let schema = r#"
+columns:
+  - name: id
+    type: int
+    required: true
+    unique: true
+    min: 1
+  - name: code
+    regex: '[A-Z]{2}'
+    enum: [AB, CD, XYZ]
+  - name: missing
+"#;
+        let violations = run(
+            schema,
+            &["id", "code"],
+            &[
+                &["1", "AB"],
+                &["1", "XYZ"],
+                &["", ""],
+                &["x", "CD"],
+                &["0", "ab"],
+            ],
+        )?;
+        let found = violations
+            .iter()
+            .map(|v| (v.line, v.column.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            vec![
+                (1, "missing"),
+                (3, "id"),
+                (3, "code"),
+                (4, "id"),
+                (5, "id"),
+                (5, "id"),
+                (6, "id"),
+                (6, "code"),
+                (6, "code"),
+            ]
+        );
+        assert_eq!(violations[1].message, "\"1\" duplicates line 2");
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_juventus() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("rcli-validate-{}.json", std::process::id()));
+        let options = CsvValidateOptions {
+            input: "assets/juventus.csv".into(),
+            schema: "fixtures/juventus.schema.yaml".into(),
+            header: true,
+            delimiter: ',',
+            output: path.to_string_lossy().into_owned(),
+            format: crate::cli::csv_options::OutputFormat::Json,
+        };
+        assert_eq!(process_csv_validate(&options)?, 0);
+        assert_eq!(fs::read_to_string(&path)?, "[]");
+        fs::remove_file(&path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,126 @@
+use anyhow::Result;
+use calamine::{open_workbook_auto_from_rs, Data, Reader};
+use chrono::Timelike;
+use serde_json::Value;
+use std::io::{Cursor, Read};
+
+use super::source::{Record, Records};
+
+/// read one sheet of an xlsx, xls or ods workbook, the first sheet if none is named
+///
+/// workbooks are zip archives that need seeking, so the input is read into memory first
+pub fn read_workbook(
+    mut reader: Box<dyn Read>,
+    sheet: Option<&str>,
+    header: bool,
+) -> Result<Records> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    let mut workbook = open_workbook_auto_from_rs(Cursor::new(bytes))?;
+
+    let names = workbook.sheet_names();
+    let name = match sheet {
+        Some(sheet) if names.iter().any(|n| n == sheet) => sheet.to_string(),
+        Some(sheet) => {
+            return Err(anyhow::format_err!(
+                "Unknown sheet: {}, available: {}",
+                sheet,
+                names.join(", ")
+            ))
+        }
+        None => names
+            .first()
+            .cloned()
+            .ok_or_else(|| anyhow::format_err!("Workbook has no sheets"))?,
+    };
+    let range = workbook.worksheet_range(&name)?;
+
+    let headers = match range.rows().next() {
+        Some(first) if header => first
+            .iter()
+            .enumerate()
+            .map(|(i, cell)| match cell {
+                Data::Empty => format!("column_{}", i + 1),
+                cell => cell.to_string(),
+            })
+            .collect(),
+        // without a header the first row is data too, skip below is 0 so it is kept
+        Some(_) => (1..=range.width())
+            .map(|i| format!("column_{}", i))
+            .collect(),
+        None => Vec::new(),
+    };
+    let skip = if header { 1 } else { 0 };
+    let records = range
+        .rows()
+        .skip(skip)
+        .map(|row| {
+            headers
+                .iter()
+                .zip(row)
+                .map(|(name, cell)| (name.clone(), to_value(cell)))
+                .collect::<Record>()
+        })
+        .collect::<Vec<_>>();
+    Ok(Box::new(records.into_iter().map(Ok)))
+}
+
+/// whole numbers become integers, dates become ISO 8601 text
+fn to_value(cell: &Data) -> Value {
+    match cell {
+        Data::Empty => Value::Null,
+        Data::Bool(b) => (*b).into(),
+        Data::Int(i) => (*i).into(),
+        Data::Float(f) if f.fract() == 0.0 && f.abs() < 1e15 => (*f as i64).into(),
+        Data::Float(f) => (*f).into(),
+        Data::String(s) | Data::DateTimeIso(s) | Data::DurationIso(s) => s.clone().into(),
+        Data::DateTime(dt) if dt.is_duration() => dt.as_f64().into(),
+        Data::DateTime(dt) => match dt.as_datetime() {
+            Some(dt) if dt.num_seconds_from_midnight() == 0 => {
+                dt.format("%Y-%m-%d").to_string().into()
+            }
+            Some(dt) => dt.format("%Y-%m-%dT%H:%M:%S").to_string().into(),
+            None => dt.as_f64().into(),
+        },
+        Data::Error(e) => e.to_string().into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cli::csv_options::OutputFormat, process::csv::writer, utils::testing::Sink};
+    use serde_json::json;
+
+    #[test]
+    fn test_workbook_round_trip() -> Result<()> {
+        let input = vec![
+            json!({"Name": "Buffon", "Age": 46, "Rating": 8.5, "Active": false}),
+            json!({"Name": "Chiellini", "Age": 40, "Club": {"name": "LAFC"}}),
+        ];
+        let sink = Sink::default();
+        let mut output = writer::new_writer(
+            OutputFormat::Xlsx,
+            Box::new(sink.clone()),
+            Default::default(),
+        );
+        for record in &input {
+            output.write_record(record.as_object().unwrap())?;
+        }
+        output.finish()?;
+
+        let bytes = sink.0.borrow().clone();
+        let records =
+            read_workbook(Box::new(Cursor::new(bytes)), None, true)?.collect::<Result<Vec<_>>>()?;
+        assert_eq!(
+            Value::Object(records[0].clone()),
+            json!({"Name": "Buffon", "Age": 46, "Rating": 8.5, "Active": false, "Club.name": null})
+        );
+        assert_eq!(records[1]["Club.name"], "LAFC");
+
+        let bytes = sink.0.borrow().clone();
+        let sheet = read_workbook(Box::new(Cursor::new(bytes)), Some("Missing"), true);
+        assert!(sheet.is_err());
+        Ok(())
+    }
+}
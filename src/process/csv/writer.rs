@@ -0,0 +1,649 @@
+use anyhow::Result;
+use indexmap::IndexSet;
+use rust_xlsxwriter::{Format, Workbook};
+use serde_json::{Map, Value};
+use std::io::Write;
+
+use super::{
+    columnar::{ColumnarFormat, ColumnarWriter},
+    flatten::{flatten, flatten_values},
+};
+use crate::cli::csv_options::{ArrayMode, Compression, CsvOptions, OutputFormat};
+
+/// write records one by one so the whole document never has to be held in memory
+pub trait RecordWriter {
+    fn write_record(&mut self, record: &Map<String, Value>) -> Result<()>;
+
+    /// write any trailing syntax and flush the underlying writer
+    fn finish(self: Box<Self>) -> Result<()>;
+}
+
+/// settings only some of the formats care about
+#[derive(Debug, Clone, Copy)]
+pub struct WriteOptions {
+    pub delimiter: u8,
+    pub arrays: ArrayMode,
+    pub compression: Compression,
+    /// buffer every record so csv and table output can union their columns
+    pub union_columns: bool,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            arrays: ArrayMode::default(),
+            compression: Compression::default(),
+            union_columns: false,
+        }
+    }
+}
+
+impl From<&CsvOptions> for WriteOptions {
+    fn from(options: &CsvOptions) -> Self {
+        Self {
+            delimiter: options.delimiter as u8,
+            arrays: options.arrays,
+            compression: options.compression,
+            union_columns: options.union_columns,
+        }
+    }
+}
+
+pub fn new_writer(
+    format: OutputFormat,
+    output: Box<dyn Write>,
+    options: WriteOptions,
+) -> Box<dyn RecordWriter> {
+    match format {
+        OutputFormat::Json => Box::new(JsonWriter::new(output)),
+        OutputFormat::Yaml => Box::new(YamlWriter::new(output)),
+        OutputFormat::Csv => Box::new(CsvWriter::new(output, options)),
+        OutputFormat::Ndjson => Box::new(NdjsonWriter::new(output)),
+        OutputFormat::Toml => Box::new(TomlWriter::new(output)),
+        OutputFormat::Table => Box::new(TableWriter::new(output, options)),
+        OutputFormat::Xlsx => Box::new(XlsxWriter::new(output, options)),
+        OutputFormat::Parquet => Box::new(ColumnarWriter::new(
+            ColumnarFormat::Parquet,
+            output,
+            options,
+        )),
+        OutputFormat::Arrow => {
+            Box::new(ColumnarWriter::new(ColumnarFormat::Arrow, output, options))
+        }
+    }
+}
+
+/// pretty printed JSON array, one element per record
+struct JsonWriter {
+    output: Box<dyn Write>,
+    count: usize,
+}
+
+impl JsonWriter {
+    fn new(output: Box<dyn Write>) -> Self {
+        Self { output, count: 0 }
+    }
+}
+
+impl RecordWriter for JsonWriter {
+    fn write_record(&mut self, record: &Map<String, Value>) -> Result<()> {
+        let sep = if self.count == 0 { "[\n" } else { ",\n" };
+        self.output.write_all(sep.as_bytes())?;
+        let json = serde_json::to_string_pretty(record)?;
+        write_indented(&mut self.output, &json, "  ", "  ")?;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        let end = if self.count == 0 { "[]" } else { "\n]" };
+        self.output.write_all(end.as_bytes())?;
+        self.output.flush()?;
+        Ok(())
+    }
+}
+
+/// YAML sequence, one item per record
+struct YamlWriter {
+    output: Box<dyn Write>,
+    count: usize,
+}
+
+impl YamlWriter {
+    fn new(output: Box<dyn Write>) -> Self {
+        Self { output, count: 0 }
+    }
+}
+
+impl RecordWriter for YamlWriter {
+    fn write_record(&mut self, record: &Map<String, Value>) -> Result<()> {
+        let yaml = serde_yaml::to_string(record)?;
+        write_indented(&mut self.output, yaml.trim_end(), "- ", "  ")?;
+        self.output.write_all(b"\n")?;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        if self.count == 0 {
+            self.output.write_all(b"[]\n")?;
+        }
+        self.output.flush()?;
+        Ok(())
+    }
+}
+
+/// newline delimited JSON, one compact record per line
+struct NdjsonWriter {
+    output: Box<dyn Write>,
+}
+
+impl NdjsonWriter {
+    fn new(output: Box<dyn Write>) -> Self {
+        Self { output }
+    }
+}
+
+impl RecordWriter for NdjsonWriter {
+    fn write_record(&mut self, record: &Map<String, Value>) -> Result<()> {
+        serde_json::to_writer(&mut self.output, record)?;
+        self.output.write_all(b"\n")?;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        self.output.flush()?;
+        Ok(())
+    }
+}
+
+/// TOML array of tables, each record becomes a `[[records]]` entry
+///
+/// TOML has no null, so null values are left out
+struct TomlWriter {
+    output: Box<dyn Write>,
+    count: usize,
+}
+
+impl TomlWriter {
+    fn new(output: Box<dyn Write>) -> Self {
+        Self { output, count: 0 }
+    }
+}
+
+impl RecordWriter for TomlWriter {
+    fn write_record(&mut self, record: &Map<String, Value>) -> Result<()> {
+        let mut doc = toml::Table::new();
+        let table = to_toml_table(record);
+        doc.insert("records".into(), toml::Value::Array(vec![table.into()]));
+        if self.count > 0 {
+            self.output.write_all(b"\n")?;
+        }
+        self.output.write_all(toml::to_string(&doc)?.as_bytes())?;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        self.output.flush()?;
+        Ok(())
+    }
+}
+
+fn to_toml_table(map: &Map<String, Value>) -> toml::Table {
+    map.iter()
+        .filter_map(|(k, v)| to_toml_value(v).map(|v| (k.clone(), v)))
+        .collect()
+}
+
+fn to_toml_value(value: &Value) -> Option<toml::Value> {
+    let value = match value {
+        Value::Null => return None,
+        Value::Bool(b) => toml::Value::Boolean(*b),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => toml::Value::Integer(i),
+            None => toml::Value::Float(n.as_f64()?),
+        },
+        Value::String(s) => toml::Value::String(s.clone()),
+        Value::Array(items) => toml::Value::Array(items.iter().filter_map(to_toml_value).collect()),
+        Value::Object(map) => toml::Value::Table(to_toml_table(map)),
+    };
+    Some(value)
+}
+
+/// rows measured before the table widths are fixed, later rows only widen their own line
+const TABLE_WIDTH_SAMPLE: usize = 100;
+
+/// aligned plain text table, streamed once the column widths are measured on the first rows
+///
+/// with `union_columns` every row is buffered so all of them line up
+struct TableWriter {
+    output: Box<dyn Write>,
+    options: WriteOptions,
+    columns: Columns,
+    /// rows waiting for the widths, `None` once the header is written
+    pending: Option<Vec<Vec<(String, String)>>>,
+    widths: Vec<usize>,
+}
+
+impl TableWriter {
+    fn new(output: Box<dyn Write>, options: WriteOptions) -> Self {
+        Self {
+            output,
+            options,
+            columns: Columns::new(options.union_columns),
+            pending: Some(Vec::new()),
+            widths: Vec::new(),
+        }
+    }
+
+    fn cells(&self, row: &[(String, String)]) -> Vec<String> {
+        self.columns
+            .cells(row)
+            .into_iter()
+            .map(|cell| cell.replace('\n', " "))
+            .collect()
+    }
+
+    /// measure the buffered rows and write them out with the header
+    fn write_pending(&mut self) -> Result<()> {
+        let Some(pending) = self.pending.take() else {
+            return Ok(());
+        };
+        // nothing to show until a record brings a column
+        if self.columns.names.is_empty() {
+            self.pending = Some(pending);
+            return Ok(());
+        }
+        let rows = pending
+            .iter()
+            .map(|row| self.cells(row))
+            .collect::<Vec<_>>();
+        self.widths = self
+            .columns
+            .names
+            .iter()
+            .map(|c| c.chars().count())
+            .collect();
+        for row in &rows {
+            for (width, cell) in self.widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+
+        let header = self.columns.names.iter().cloned().collect::<Vec<_>>();
+        write_table_row(&mut self.output, &header, &self.widths)?;
+        let rule = self
+            .widths
+            .iter()
+            .map(|w| "-".repeat(*w))
+            .collect::<Vec<_>>()
+            .join("-+-");
+        writeln!(self.output, "{}", rule)?;
+        for row in &rows {
+            write_table_row(&mut self.output, row, &self.widths)?;
+        }
+        Ok(())
+    }
+}
+
+impl RecordWriter for TableWriter {
+    fn write_record(&mut self, record: &Map<String, Value>) -> Result<()> {
+        let row = flatten(record, self.options.arrays);
+        self.columns.add(&row)?;
+        match &mut self.pending {
+            Some(pending) => {
+                pending.push(row);
+                if !self.options.union_columns && pending.len() >= TABLE_WIDTH_SAMPLE {
+                    self.write_pending()?;
+                }
+            }
+            None => {
+                let cells = self.cells(&row);
+                write_table_row(&mut self.output, &cells, &self.widths)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        self.write_pending()?;
+        self.output.flush()?;
+        Ok(())
+    }
+}
+
+fn write_table_row(output: &mut dyn Write, cells: &[String], widths: &[usize]) -> Result<()> {
+    let line = cells
+        .iter()
+        .zip(widths)
+        .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+        .collect::<Vec<_>>()
+        .join(" | ");
+    writeln!(output, "{}", line.trim_end())?;
+    Ok(())
+}
+
+/// single sheet workbook with a bold header row, flattened like CSV but cells keep their type
+///
+/// the archive can only be written once complete, so rows are buffered
+struct XlsxWriter {
+    output: Box<dyn Write>,
+    options: WriteOptions,
+    columns: IndexSet<String>,
+    rows: Vec<Vec<(String, Value)>>,
+}
+
+impl XlsxWriter {
+    fn new(output: Box<dyn Write>, options: WriteOptions) -> Self {
+        Self {
+            output,
+            options,
+            columns: IndexSet::new(),
+            rows: Vec::new(),
+        }
+    }
+}
+
+impl RecordWriter for XlsxWriter {
+    fn write_record(&mut self, record: &Map<String, Value>) -> Result<()> {
+        let row = flatten_values(record, self.options.arrays);
+        for (key, _) in &row {
+            if !self.columns.contains(key) {
+                self.columns.insert(key.clone());
+            }
+        }
+        self.rows.push(row);
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        let mut workbook = Workbook::new();
+        let sheet = workbook.add_worksheet();
+        let bold = Format::new().set_bold();
+        for (col, name) in self.columns.iter().enumerate() {
+            sheet.write_string_with_format(0, col as u16, name, &bold)?;
+        }
+        for (row, cells) in self.rows.iter().enumerate() {
+            let row = row as u32 + 1;
+            for (key, value) in cells {
+                let Some(col) = self.columns.get_index_of(key) else {
+                    continue;
+                };
+                let col = col as u16;
+                match value {
+                    Value::Null => {}
+                    Value::Bool(b) => {
+                        sheet.write_boolean(row, col, *b)?;
+                    }
+                    Value::Number(n) => {
+                        sheet.write_number(row, col, n.as_f64().unwrap_or_default())?;
+                    }
+                    Value::String(s) => {
+                        sheet.write_string(row, col, s)?;
+                    }
+                    value => {
+                        sheet.write_string(row, col, value.to_string())?;
+                    }
+                }
+            }
+        }
+        self.output.write_all(&workbook.save_to_buffer()?)?;
+        self.output.flush()?;
+        Ok(())
+    }
+}
+
+/// output columns of the csv and table writers
+///
+/// taken from the first record so rows can be streamed, or the union of all keys in first-seen
+/// order when `union_columns` buffers the rows
+struct Columns {
+    names: IndexSet<String>,
+    union: bool,
+    /// set once a record brings at least one column, later keys must already be known unless unioning
+    fixed: bool,
+}
+
+impl Columns {
+    fn new(union: bool) -> Self {
+        Self {
+            names: IndexSet::new(),
+            union,
+            fixed: false,
+        }
+    }
+
+    fn add(&mut self, row: &[(String, String)]) -> Result<()> {
+        for (key, _) in row {
+            if self.names.contains(key) {
+                continue;
+            }
+            if self.fixed {
+                return Err(anyhow::format_err!(
+                    "Column {} is not in the header taken from the first record, use --union-columns to buffer the records and union their columns",
+                    key
+                ));
+            }
+            self.names.insert(key.clone());
+        }
+        self.fixed = !self.union && !self.names.is_empty();
+        Ok(())
+    }
+
+    /// the row's values in column order, missing columns are empty
+    fn cells(&self, row: &[(String, String)]) -> Vec<String> {
+        let mut cells = vec![String::new(); self.names.len()];
+        for (key, value) in row {
+            if let Some(index) = self.names.get_index_of(key) {
+                cells[index] = value.clone();
+            }
+        }
+        cells
+    }
+}
+
+/// flattened CSV, the header is written from the first record and rows are streamed
+///
+/// with `union_columns` rows are buffered until every column is known
+struct CsvWriter {
+    writer: csv::Writer<Box<dyn Write>>,
+    options: WriteOptions,
+    columns: Columns,
+    /// only used with `union_columns`
+    rows: Vec<Vec<(String, String)>>,
+}
+
+impl CsvWriter {
+    fn new(output: Box<dyn Write>, options: WriteOptions) -> Self {
+        Self {
+            writer: csv::WriterBuilder::new()
+                .delimiter(options.delimiter)
+                .from_writer(output),
+            options,
+            columns: Columns::new(options.union_columns),
+            rows: Vec::new(),
+        }
+    }
+}
+
+impl RecordWriter for CsvWriter {
+    fn write_record(&mut self, record: &Map<String, Value>) -> Result<()> {
+        let row = flatten(record, self.options.arrays);
+        let first = self.columns.names.is_empty();
+        self.columns.add(&row)?;
+        if self.options.union_columns {
+            self.rows.push(row);
+            return Ok(());
+        }
+        if self.columns.names.is_empty() {
+            return Ok(());
+        }
+        if first {
+            self.writer.write_record(&self.columns.names)?;
+        }
+        self.writer.write_record(self.columns.cells(&row))?;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        if !self.rows.is_empty() && !self.columns.names.is_empty() {
+            self.writer.write_record(&self.columns.names)?;
+            for row in &self.rows {
+                self.writer.write_record(self.columns.cells(row))?;
+            }
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+fn write_indented(output: &mut dyn Write, text: &str, first: &str, rest: &str) -> Result<()> {
+    for (i, line) in text.lines().enumerate() {
+        if i > 0 {
+            output.write_all(b"\n")?;
+        }
+        let prefix = if i == 0 { first } else { rest };
+        output.write_all(prefix.as_bytes())?;
+        output.write_all(line.as_bytes())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::testing::Sink;
+
+    fn write_all(format: OutputFormat, records: &[Value]) -> Result<String> {
+        write_with(format, WriteOptions::default(), records)
+    }
+
+    fn write_with(
+        format: OutputFormat,
+        options: WriteOptions,
+        records: &[Value],
+    ) -> Result<String> {
+        let sink = Sink::default();
+        let mut writer = new_writer(format, Box::new(sink.clone()), options);
+        for record in records {
+            writer.write_record(record.as_object().unwrap())?;
+        }
+        writer.finish()?;
+        let output = sink.0.borrow().clone();
+        Ok(String::from_utf8(output)?)
+    }
+
+    #[test]
+    fn test_json_writer_matches_serde() -> Result<()> {
+        let records = vec![
+            serde_json::json!({"name": "a", "age": "1"}),
+            serde_json::json!({"name": "b", "age": "2"}),
+        ];
+        let output = write_all(OutputFormat::Json, &records)?;
+        assert_eq!(output, serde_json::to_string_pretty(&records)?);
+        assert_eq!(write_all(OutputFormat::Json, &[])?, "[]");
+        Ok(())
+    }
+
+    #[test]
+    fn test_yaml_writer_matches_serde() -> Result<()> {
+        let records = vec![
+            serde_json::json!({"name": "a", "bio": "line1\nline2"}),
+            serde_json::json!({"name": "b", "bio": ""}),
+        ];
+        let output = write_all(OutputFormat::Yaml, &records)?;
+        assert_eq!(output, serde_yaml::to_string(&records)?);
+        assert_eq!(write_all(OutputFormat::Yaml, &[])?, "[]\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_ndjson_and_toml_writers() -> Result<()> {
+        let records = vec![
+            serde_json::json!({"name": "a", "age": 1, "note": null}),
+            serde_json::json!({"name": "b", "age": 2.5, "address": {"city": "Turin"}}),
+        ];
+        let output = write_all(OutputFormat::Ndjson, &records)?;
+        assert_eq!(
+            output,
+            "{\"name\":\"a\",\"age\":1,\"note\":null}\n{\"name\":\"b\",\"age\":2.5,\"address\":{\"city\":\"Turin\"}}\n"
+        );
+
+        let output = write_all(OutputFormat::Toml, &records)?;
+        let doc: toml::Table = output.parse()?;
+        let parsed = doc["records"].as_array().unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0]["age"].as_integer(), Some(1));
+        assert!(parsed[0].get("note").is_none());
+        assert_eq!(parsed[1]["address"]["city"].as_str(), Some("Turin"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_writer() -> Result<()> {
+        let records = vec![
+            serde_json::json!({"name": "alice", "age": 30}),
+            serde_json::json!({"name": "bob", "age": 4}),
+        ];
+        let output = write_all(OutputFormat::Table, &records)?;
+        assert_eq!(output, "name  | age\n------+----\nalice | 30\nbob   | 4\n");
+        assert_eq!(write_all(OutputFormat::Table, &[])?, "");
+        Ok(())
+    }
+
+    #[test]
+    fn test_csv_writer_unions_columns() -> Result<()> {
+        let records = vec![
+            serde_json::json!({"name": "a", "address": {"city": "Turin"}}),
+            serde_json::json!({"name": "b", "tags": ["x", "y"]}),
+        ];
+        let options = WriteOptions {
+            union_columns: true,
+            ..Default::default()
+        };
+        let output = write_with(OutputFormat::Csv, options, &records)?;
+        assert_eq!(
+            output,
+            "name,address.city,tags[0],tags[1]\na,Turin,,\nb,,x,y\n"
+        );
+        let output = write_with(OutputFormat::Table, options, &records)?;
+        assert_eq!(
+            output.lines().next(),
+            Some("name | address.city | tags[0] | tags[1]")
+        );
+        assert!(write_all(OutputFormat::Csv, &records).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_csv_writer_empty_first_record() -> Result<()> {
+        let records = vec![serde_json::json!({}), serde_json::json!({"a": 1})];
+        assert_eq!(write_all(OutputFormat::Csv, &records)?, "a\n1\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_csv_writer_streams_rows() -> Result<()> {
+        let sink = Sink::default();
+        let mut writer = new_writer(
+            OutputFormat::Csv,
+            Box::new(sink.clone()),
+            WriteOptions::default(),
+        );
+        writer.write_record(
+            serde_json::json!({"name": "a", "age": 1})
+                .as_object()
+                .unwrap(),
+        )?;
+        writer.write_record(serde_json::json!({"name": "b"}).as_object().unwrap())?;
+        assert!(writer
+            .write_record(serde_json::json!({"city": "Turin"}).as_object().unwrap())
+            .is_err());
+        writer.finish()?;
+        // missing columns are left empty, the header comes from the first record
+        assert_eq!(sink.0.borrow().as_slice(), b"name,age\na,1\nb,\n");
+        Ok(())
+    }
+}
@@ -0,0 +1,553 @@
+use anyhow::Result;
+use chrono::NaiveDate;
+use rand::{distributions::WeightedIndex, prelude::*, rngs::StdRng};
+use serde::Deserialize;
+use serde_json::{Map, Number, Value};
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use super::csv::writer::{self, RecordWriter};
+use crate::{
+    cli::fake_options::FakeOptions,
+    utils::{format_date, get_writer},
+};
+
+const FIRST_NAMES: [&str; 32] = [
+    "Alice", "Andrea", "Ben", "Carlos", "Chen", "Chiara", "Daniel", "Elena", "Emma", "Fatima",
+    "Federico", "Giorgio", "Hannah", "Hiro", "Isabel", "James", "Julia", "Kenji", "Laura", "Leon",
+    "Lucas", "Maria", "Mei", "Noah", "Olivia", "Paolo", "Priya", "Sara", "Sofia", "Tom", "Wei",
+    "Yusuf",
+];
+
+const LAST_NAMES: [&str; 32] = [
+    "Bianchi",
+    "Brown",
+    "Costa",
+    "Dubois",
+    "Fernandez",
+    "Fischer",
+    "Garcia",
+    "Green",
+    "Hansen",
+    "Ito",
+    "Jensen",
+    "Kim",
+    "Kowalski",
+    "Li",
+    "Lopez",
+    "Martin",
+    "Moreau",
+    "Muller",
+    "Nakamura",
+    "Novak",
+    "Patel",
+    "Rossi",
+    "Santos",
+    "Schmidt",
+    "Silva",
+    "Smith",
+    "Tanaka",
+    "Taylor",
+    "Wang",
+    "Williams",
+    "Wilson",
+    "Zhang",
+];
+
+const CITIES: [&str; 16] = [
+    "Amsterdam",
+    "Barcelona",
+    "Berlin",
+    "Buenos Aires",
+    "Chicago",
+    "Lisbon",
+    "London",
+    "Madrid",
+    "Milan",
+    "Osaka",
+    "Paris",
+    "Seoul",
+    "Shanghai",
+    "Sydney",
+    "Toronto",
+    "Turin",
+];
+
+const COUNTRIES: [&str; 16] = [
+    "Argentina",
+    "Australia",
+    "Brazil",
+    "Canada",
+    "China",
+    "France",
+    "Germany",
+    "India",
+    "Italy",
+    "Japan",
+    "Netherlands",
+    "Portugal",
+    "South Korea",
+    "Spain",
+    "United Kingdom",
+    "United States",
+];
+
+/// reserved for documentation, so generated addresses never reach a real inbox
+const EMAIL_DOMAINS: [&str; 3] = ["example.com", "example.net", "example.org"];
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Schema {
+    tables: Vec<Table>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Table {
+    name: String,
+    /// overrides `--rows`
+    #[serde(default)]
+    rows: Option<usize>,
+    columns: Vec<Column>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Column {
+    name: String,
+    /// share of the rows left empty, between 0 and 1
+    #[serde(default)]
+    null_rate: f64,
+    #[serde(flatten)]
+    kind: Kind,
+}
+
+/// `deny_unknown_fields` can't be set on `Column` because of the flatten, so unknown keys are
+/// rejected here, which is also why variants without settings are empty structs
+#[derive(Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+enum Kind {
+    /// `start`, `start + 1`, ... handy as a primary key
+    Sequence {
+        #[serde(default = "default_start")]
+        start: i64,
+    },
+    Uuid {},
+    FirstName {},
+    LastName {},
+    Name {},
+    Email {},
+    City {},
+    Country {},
+    Integer {
+        min: i64,
+        max: i64,
+    },
+    Float {
+        min: f64,
+        max: f64,
+        #[serde(default = "default_decimals")]
+        decimals: u32,
+    },
+    Boolean {
+        #[serde(default = "default_probability")]
+        probability: f64,
+    },
+    /// a day between `from` and `to`, both `%Y-%m-%d` and inclusive
+    Date {
+        from: String,
+        to: String,
+        #[serde(default = "default_date_format")]
+        format: String,
+    },
+    Enum {
+        values: Vec<Value>,
+        #[serde(default)]
+        weights: Option<Vec<f64>>,
+    },
+    /// a value of `column` from one of the rows generated for an earlier `table`
+    Reference {
+        table: String,
+        column: String,
+    },
+}
+
+fn default_start() -> i64 {
+    1
+}
+
+fn default_decimals() -> u32 {
+    2
+}
+
+fn default_probability() -> f64 {
+    0.5
+}
+
+fn default_date_format() -> String {
+    "%Y-%m-%d".to_string()
+}
+
+/// a column with its settings checked and everything it needs resolved up front
+enum Generator<'a> {
+    Sequence(i64),
+    Uuid,
+    Pick(&'a [&'static str]),
+    Name,
+    Email,
+    Integer(i64, i64),
+    Float(f64, f64, u32),
+    Boolean(f64),
+    Date(NaiveDate, i64, &'a str),
+    Enum(&'a [Value], Option<WeightedIndex<f64>>),
+    Reference(&'a [Value]),
+}
+
+/// write every table of the schema, returning the files written when there are several
+pub fn process_fake(options: &FakeOptions) -> Result<Vec<PathBuf>> {
+    let schema: Schema = serde_yaml::from_str(&fs::read_to_string(&options.schema)?)?;
+    let mut rng = match options.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+
+    let mut paths = Vec::new();
+    if schema.tables.len() == 1 {
+        let output = options.output.as_deref().unwrap_or("-");
+        let output = get_writer(output)?;
+        let writer = writer::new_writer(options.format, output, Default::default());
+        let mut writer = Some(writer);
+        generate(&schema, options.rows, &mut rng, |_| {
+            writer
+                .take()
+                .ok_or_else(|| anyhow::format_err!("Output already taken"))
+        })?;
+    } else {
+        let dir = PathBuf::from(options.output.as_deref().unwrap_or("."));
+        fs::create_dir_all(&dir)?;
+        generate(&schema, options.rows, &mut rng, |name| {
+            let path = dir.join(format!("{}.{}", name, options.format.extension()));
+            let output = get_writer(&path.to_string_lossy())?;
+            paths.push(path);
+            Ok(writer::new_writer(
+                options.format,
+                output,
+                Default::default(),
+            ))
+        })?;
+    }
+    Ok(paths)
+}
+
+/// generate the tables in order, each written to the writer `open` returns for it
+///
+/// only the columns other tables reference are kept in memory, everything else is streamed
+fn generate(
+    schema: &Schema,
+    rows: usize,
+    rng: &mut StdRng,
+    mut open: impl FnMut(&str) -> Result<Box<dyn RecordWriter>>,
+) -> Result<()> {
+    if schema.tables.is_empty() {
+        return Err(anyhow::format_err!("Schema has no tables"));
+    }
+    // names become file names when there are several tables
+    for table in &schema.tables {
+        let valid = !table.name.is_empty()
+            && table
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if !valid {
+            return Err(anyhow::format_err!(
+                "Invalid table name {:?}, use letters, digits, `_` and `-`",
+                table.name
+            ));
+        }
+    }
+    let mut referenced: HashMap<(&str, &str), Vec<Value>> = HashMap::new();
+    for table in &schema.tables {
+        for column in &table.columns {
+            if let Kind::Reference { table, column } = &column.kind {
+                referenced.insert((table, column), Vec::new());
+            }
+        }
+    }
+
+    for table in &schema.tables {
+        let rows = table.rows.unwrap_or(rows);
+        let generators = table
+            .columns
+            .iter()
+            .map(|column| {
+                Generator::try_new(column, &referenced).map_err(|e| {
+                    anyhow::format_err!("Table {}, column {}: {}", table.name, column.name, e)
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // filled after the table is written, the generators borrow the values of earlier tables
+        let mut kept = Vec::new();
+        let mut writer = open(&table.name)?;
+        for row in 0..rows {
+            let mut record = Map::with_capacity(table.columns.len());
+            for (column, generator) in table.columns.iter().zip(&generators) {
+                let value = if column.null_rate > 0.0 && rng.gen_bool(column.null_rate) {
+                    Value::Null
+                } else {
+                    generator.generate(row, rng).map_err(|e| {
+                        anyhow::format_err!("Table {}, column {}: {}", table.name, column.name, e)
+                    })?
+                };
+                // nulls are never picked as a foreign key
+                let key = (table.name.as_str(), column.name.as_str());
+                if !value.is_null() && referenced.contains_key(&key) {
+                    kept.push((key, value.clone()));
+                }
+                record.insert(column.name.clone(), value);
+            }
+            writer.write_record(&record)?;
+        }
+        writer.finish()?;
+
+        drop(generators);
+        for (key, value) in kept {
+            referenced.entry(key).or_default().push(value);
+        }
+    }
+    Ok(())
+}
+
+impl<'a> Generator<'a> {
+    fn try_new(
+        column: &'a Column,
+        referenced: &'a HashMap<(&str, &str), Vec<Value>>,
+    ) -> Result<Self> {
+        if !(0.0..=1.0).contains(&column.null_rate) {
+            return Err(anyhow::format_err!("null_rate must be between 0 and 1"));
+        }
+        let generator = match &column.kind {
+            Kind::Sequence { start } => Generator::Sequence(*start),
+            Kind::Uuid {} => Generator::Uuid,
+            Kind::FirstName {} => Generator::Pick(&FIRST_NAMES),
+            Kind::LastName {} => Generator::Pick(&LAST_NAMES),
+            Kind::Name {} => Generator::Name,
+            Kind::Email {} => Generator::Email,
+            Kind::City {} => Generator::Pick(&CITIES),
+            Kind::Country {} => Generator::Pick(&COUNTRIES),
+            Kind::Integer { min, max } if min <= max => Generator::Integer(*min, *max),
+            Kind::Float { min, max, .. } if !(max - min).is_finite() => {
+                return Err(anyhow::format_err!("min and max must be finite numbers"))
+            }
+            Kind::Float { min, max, decimals } if min <= max => {
+                Generator::Float(*min, *max, *decimals)
+            }
+            Kind::Integer { .. } | Kind::Float { .. } => {
+                return Err(anyhow::format_err!("min is greater than max"))
+            }
+            Kind::Boolean { probability } if (0.0..=1.0).contains(probability) => {
+                Generator::Boolean(*probability)
+            }
+            Kind::Boolean { .. } => {
+                return Err(anyhow::format_err!("probability must be between 0 and 1"))
+            }
+            Kind::Date { from, to, format } => {
+                let parse = |date: &str| {
+                    NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| {
+                        anyhow::format_err!("Invalid date {}, expected %Y-%m-%d", date)
+                    })
+                };
+                let (from, to) = (parse(from)?, parse(to)?);
+                if from > to {
+                    return Err(anyhow::format_err!("from is after to"));
+                }
+                // checked once so `generate` can't hit a bad format
+                format_date(from.format(format), format)?;
+                Generator::Date(from, (to - from).num_days(), format)
+            }
+            Kind::Enum { values, .. } if values.is_empty() => {
+                return Err(anyhow::format_err!("enum has no values"))
+            }
+            Kind::Enum { values, weights } => {
+                let weights = match weights {
+                    Some(weights) if weights.len() != values.len() => {
+                        return Err(anyhow::format_err!(
+                            "{} weights for {} values",
+                            weights.len(),
+                            values.len()
+                        ))
+                    }
+                    Some(weights) => Some(WeightedIndex::new(weights)?),
+                    None => None,
+                };
+                Generator::Enum(values, weights)
+            }
+            Kind::Reference { table, column } => {
+                let values = &referenced[&(table.as_str(), column.as_str())];
+                if values.is_empty() {
+                    return Err(anyhow::format_err!(
+                        "{}.{} has no values to reference, it must be generated by an earlier table",
+                        table,
+                        column
+                    ));
+                }
+                Generator::Reference(values)
+            }
+        };
+        Ok(generator)
+    }
+
+    fn generate(&self, row: usize, rng: &mut StdRng) -> Result<Value> {
+        let value = match self {
+            Generator::Sequence(start) => i64::try_from(row)
+                .ok()
+                .and_then(|row| start.checked_add(row))
+                .ok_or_else(|| anyhow::format_err!("sequence overflows at row {}", row))?
+                .into(),
+            Generator::Uuid => uuid(rng).into(),
+            Generator::Pick(values) => pick(values, rng).into(),
+            Generator::Name => {
+                format!("{} {}", pick(&FIRST_NAMES, rng), pick(&LAST_NAMES, rng)).into()
+            }
+            Generator::Email => format!(
+                "{}.{}{}@{}",
+                pick(&FIRST_NAMES, rng).to_lowercase(),
+                pick(&LAST_NAMES, rng).to_lowercase(),
+                rng.gen_range(1..100),
+                pick(&EMAIL_DOMAINS, rng)
+            )
+            .into(),
+            Generator::Integer(min, max) => rng.gen_range(*min..=*max).into(),
+            Generator::Float(min, max, decimals) => {
+                let scale = 10f64.powi(*decimals as i32);
+                let n = (rng.gen_range(*min..=*max) * scale).round() / scale;
+                Number::from_f64(n).map_or(Value::Null, Value::Number)
+            }
+            Generator::Boolean(probability) => rng.gen_bool(*probability).into(),
+            Generator::Date(from, days, format) => {
+                let date = *from + chrono::Duration::days(rng.gen_range(0..=*days));
+                date.format(format).to_string().into()
+            }
+            Generator::Enum(values, Some(weights)) => values[weights.sample(rng)].clone(),
+            Generator::Enum(values, None) | Generator::Reference(values) => {
+                values[rng.gen_range(0..values.len())].clone()
+            }
+        };
+        Ok(value)
+    }
+}
+
+fn pick(values: &[&'static str], rng: &mut StdRng) -> &'static str {
+    values[rng.gen_range(0..values.len())]
+}
+
+/// a version 4 UUID, drawn from the seeded generator so it is reproducible too
+fn uuid(rng: &mut StdRng) -> String {
+    let mut bytes: [u8; 16] = rng.gen();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex = bytes
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cli::csv_options::OutputFormat, utils::testing::Sink};
+
+    fn run(schema: &Schema, seed: u64) -> Result<HashMap<String, Vec<Value>>> {
+        let mut sinks = Vec::new();
+        generate(schema, 50, &mut StdRng::seed_from_u64(seed), |name| {
+            let sink = Sink::default();
+            sinks.push((name.to_string(), sink.clone()));
+            Ok(writer::new_writer(
+                OutputFormat::Ndjson,
+                Box::new(sink),
+                Default::default(),
+            ))
+        })?;
+        let mut tables = HashMap::new();
+        for (name, sink) in sinks {
+            let text = String::from_utf8(sink.0.borrow().clone())?;
+            let rows = text
+                .lines()
+                .map(serde_json::from_str)
+                .collect::<Result<Vec<Value>, _>>()?;
+            tables.insert(name, rows);
+        }
+        Ok(tables)
+    }
+
+    #[test]
+    fn test_generate() -> Result<()> {
+        let schema: Schema =
+            serde_yaml::from_str(&fs::read_to_string("fixtures/people.fake.yaml")?)?;
+        let tables = run(&schema, 42)?;
+        assert_eq!(tables, run(&schema, 42)?);
+        assert_ne!(tables, run(&schema, 7)?);
+
+        let (teams, people) = (&tables["teams"], &tables["people"]);
+        assert_eq!((teams.len(), people.len()), (8, 50));
+        assert_eq!(teams[0]["id"], 1);
+        for person in people {
+            let team = &person["team_id"];
+            assert!(team.is_null() || teams.iter().any(|t| &t["id"] == team));
+            let id = person["id"].as_str().unwrap();
+            assert_eq!((id.len(), &id[14..15]), (36, "4"));
+            let rating = person["rating"].as_f64().unwrap();
+            assert!((5.0..=10.0).contains(&rating));
+            assert!(person["email"].as_str().unwrap().contains("@example."));
+            let born = person["born"].as_str().unwrap();
+            assert!(("1970-01-01"..="2005-12-31").contains(&born));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_schema() {
+        let schema = |yaml: &str| serde_yaml::from_str::<Schema>(yaml).unwrap();
+        let later = schema(
+            "tables:
+  - name: people
+    columns: [{name: team, kind: reference, table: teams, column: id}]
+  - name: teams
+    columns: [{name: id, kind: sequence}]",
+        );
+        assert!(run(&later, 1).is_err());
+        let range =
+            schema("tables: [{name: t, columns: [{name: n, kind: integer, min: 3, max: 1}]}]");
+        assert!(run(&range, 1).is_err());
+        for (min, max) in [(".nan", "1"), ("0", ".inf"), ("-1.5e308", "1.5e308")] {
+            let yaml = format!(
+                "tables: [{{name: t, columns: [{{name: n, kind: float, min: {}, max: {}}}]}}]",
+                min, max
+            );
+            assert!(run(&schema(&yaml), 1).is_err(), "{} {}", min, max);
+        }
+        let path = schema("tables: [{name: ../x, columns: [{name: id, kind: sequence}]}]");
+        assert!(run(&path, 1).is_err());
+        let overflow = schema(
+            "tables: [{name: t, columns: [{name: id, kind: sequence, start: 9223372036854775807}]}]",
+        );
+        assert!(run(&overflow, 1).is_err());
+        assert!(serde_yaml::from_str::<Schema>(
+            "tables: [{name: t, columns: [{name: n, kind: nope}]}]"
+        )
+        .is_err());
+        for column in [
+            "{name: n, kind: uuid, nul_rate: 0.5}",
+            "{name: n, kind: integer, min: 1, max: 2, step: 1}",
+        ] {
+            let yaml = format!("tables: [{{name: t, columns: [{}]}}]", column);
+            assert!(serde_yaml::from_str::<Schema>(&yaml).is_err(), "{}", column);
+        }
+    }
+}
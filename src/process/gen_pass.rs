@@ -1,26 +1,526 @@
-use crate::cli::gen_pass_options::GenPassOptions;
+use crate::cli::gen_pass_options::{GenPassOptions, PassFormat, Pattern, PatternPart};
 use anyhow::Result;
-use rand::Rng;
+use rand::{seq::SliceRandom, Rng};
+use serde_json::json;
+use std::{collections::HashSet, sync::LazyLock};
+
+use super::{pass::process_strength, policy::Policy};
+
+/// the EFF large wordlist, one `<dice rolls>\t<word>` per line
+const WORDLIST: &str = include_str!("../../assets/eff_large_wordlist.txt");
+
+/// the words of `WORDLIST`, split out once and shared by every passphrase
+static WORDS: LazyLock<Vec<&'static str>> = LazyLock::new(|| {
+    WORDLIST
+        .lines()
+        .filter_map(|line| line.split('\t').nth(1))
+        .collect()
+});
+
+const UPPERCASE: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const LOWERCASE: &[u8] = b"abcdefghijklmnopqrstuvwxyz";
+const NUMBERS: &[u8] = b"0123456789";
+const SYMBOLS: &[u8] = b"@#$%^&*?";
+const CONSONANTS: &[u8] = b"bcdfghjklmnpqrstvwxyz";
+const VOWELS: &[u8] = b"aeiou";
+
+/// look-alike characters left out by `--no-ambiguous`
+const AMBIGUOUS: &[u8] = b"0O1lI";
+
+/// how many candidates `--min-score` and `--policy` try before giving up
+const MAX_ATTEMPTS: usize = 1000;
+
+/// what a policy changes about generation, everything else comes from the options
+struct Settings {
+    length: usize,
+    symbols: Vec<char>,
+    policy: Option<Policy>,
+}
+
+impl Settings {
+    fn try_new(options: &GenPassOptions) -> Result<Self> {
+        let policy = options.policy.as_deref().map(Policy::load).transpose()?;
+        let length = match &policy {
+            Some(policy) => policy.clamp_length(options.length),
+            None => options.length,
+        };
+        let symbols = match policy.as_ref().and_then(|p| p.symbols.as_ref()) {
+            Some(symbols) => symbols.chars().collect(),
+            None => SYMBOLS.iter().map(|c| char::from(*c)).collect(),
+        };
+        Ok(Self {
+            length,
+            symbols,
+            policy,
+        })
+    }
+}
 
 pub fn process_genpass(options: &GenPassOptions) -> Result<String> {
+    let settings = Settings::try_new(options)?;
+    generate_valid(options, &settings, &mut rand::thread_rng())
+}
+
+/// `--count` passwords, with `--unique` no password is repeated
+pub fn process_genpass_batch(options: &GenPassOptions) -> Result<Vec<String>> {
+    let mut passwords = Vec::with_capacity(options.count);
+    let mut seen = HashSet::new();
+    // consecutive duplicates, a small pattern or charset may not have enough passwords
+    let mut misses = 0;
+    let settings = Settings::try_new(options)?;
     let mut rng = rand::thread_rng();
-    let mut password = String::new();
-    let mut chars = Vec::new();
-    if !options.no_uppercase {
-        chars.extend_from_slice(b"ABCDEFGHIJKLMNOPQRSTUVWXYZ");
+    while passwords.len() < options.count {
+        let password = generate_valid(options, &settings, &mut rng)?;
+        if options.unique && !seen.insert(password.clone()) {
+            misses += 1;
+            if misses == MAX_ATTEMPTS {
+                return Err(anyhow::format_err!(
+                    "Only found {} unique passwords, the options allow too few",
+                    passwords.len()
+                ));
+            }
+            continue;
+        }
+        misses = 0;
+        passwords.push(password);
+    }
+    Ok(passwords)
+}
+
+/// render passwords as lines, or as JSON or CSV with the strength of each one
+pub fn process_genpass_report(passwords: &[String], format: PassFormat) -> Result<String> {
+    let strengths = passwords.iter().map(|p| process_strength(p));
+    let ret = match format {
+        PassFormat::Plain => passwords.iter().map(|p| format!("{}\n", p)).collect(),
+        PassFormat::Json => {
+            let rows = passwords
+                .iter()
+                .zip(strengths)
+                .map(|(password, strength)| {
+                    json!({
+                        "password": password,
+                        "score": strength.score,
+                        "entropy": (strength.entropy * 100.0).round() / 100.0,
+                        "crack_time": strength.crack_time,
+                    })
+                })
+                .collect::<Vec<_>>();
+            serde_json::to_string_pretty(&rows)? + "\n"
+        }
+        PassFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            writer.write_record(["password", "score", "entropy", "crack_time"])?;
+            for (password, strength) in passwords.iter().zip(strengths) {
+                writer.write_record([
+                    password,
+                    &strength.score.to_string(),
+                    &format!("{:.2}", strength.entropy),
+                    &strength.crack_time,
+                ])?;
+            }
+            String::from_utf8(writer.into_inner()?)?
+        }
+    };
+    Ok(ret)
+}
+
+/// regenerate until the password reaches `--min-score` and passes the policy
+fn generate_valid(
+    options: &GenPassOptions,
+    settings: &Settings,
+    rng: &mut impl Rng,
+) -> Result<String> {
+    let policy = settings.policy.as_ref();
+    if options.min_score.is_none() && policy.is_none() {
+        return generate(options, settings, rng);
+    }
+    let mut violations = Vec::new();
+    for _ in 0..MAX_ATTEMPTS {
+        let password = generate(options, settings, rng)?;
+        let strength = process_strength(&password);
+        violations = policy
+            .map(|p| p.violations(&password, &strength))
+            .unwrap_or_default();
+        if let Some(min) = options.min_score.filter(|min| strength.score < *min) {
+            violations.push(format!("score {} is below {}", strength.score, min));
+        }
+        if violations.is_empty() {
+            return Ok(password);
+        }
+    }
+    Err(anyhow::format_err!(
+        "No password met the requirements in {} attempts, the last one failed with: {}",
+        MAX_ATTEMPTS,
+        violations.join(", ")
+    ))
+}
+
+fn generate(options: &GenPassOptions, settings: &Settings, rng: &mut impl Rng) -> Result<String> {
+    match (options.words, &options.pattern) {
+        (Some(words), _) => passphrase(words, options, &settings.symbols, rng),
+        (None, Some(pattern)) => from_pattern(pattern, options, &settings.symbols, rng),
+        (None, None) => random_password(options, settings, rng),
+    }
+}
+
+/// `--min-each` characters of every enabled class, the rest drawn from all of them
+fn random_password(
+    options: &GenPassOptions,
+    settings: &Settings,
+    rng: &mut impl Rng,
+) -> Result<String> {
+    let length = settings.length;
+    let classes = match &options.charset {
+        Some(charset) => vec![charset.0.clone()],
+        None => [
+            (options.no_uppercase, UPPERCASE),
+            (options.no_lowercase, LOWERCASE),
+            (options.no_numbers, NUMBERS),
+        ]
+        .into_iter()
+        .filter(|(disabled, _)| !disabled)
+        .map(|(_, class)| class.iter().map(|c| char::from(*c)).collect())
+        // a policy allowing no symbols disables them too
+        .chain(
+            (!options.no_symbols && !settings.symbols.is_empty()).then(|| settings.symbols.clone()),
+        )
+        .collect(),
+    };
+    let classes = classes
+        .into_iter()
+        .map(|class| unambiguous(class, options))
+        .collect::<Vec<_>>();
+    if classes.is_empty() {
+        return Err(anyhow::format_err!(
+            "Every character class is disabled, enable at least one"
+        ));
+    }
+    if classes.iter().any(|class| class.is_empty()) {
+        return Err(anyhow::format_err!(
+            "No characters left after --no-ambiguous"
+        ));
+    }
+    if options.min_each * classes.len() > length {
+        return Err(anyhow::format_err!(
+            "A password of length {} cannot hold {} characters from each of {} classes",
+            length,
+            options.min_each,
+            classes.len()
+        ));
+    }
+
+    // the class each position is drawn from, `None` for any class
+    let mut slots = (0..classes.len())
+        .flat_map(|class| std::iter::repeat_n(Some(class), options.min_each))
+        .collect::<Vec<_>>();
+    slots.resize(length, None);
+    slots.shuffle(rng);
+
+    let all = classes.concat();
+    let mut password = Vec::with_capacity(length);
+    for slot in slots {
+        let pool = slot.map_or(&all, |class| &classes[class]);
+        let c = pick(&password, pool, options, rng)?;
+        password.push(c);
+    }
+    Ok(password.into_iter().collect())
+}
+
+/// fill in the placeholders of a template, e.g. `Cvcc-9999` gives `Bido-4821`
+fn from_pattern(
+    pattern: &Pattern,
+    options: &GenPassOptions,
+    symbols: &[char],
+    rng: &mut impl Rng,
+) -> Result<String> {
+    let letters = |class: &[u8], upper: bool| {
+        class
+            .iter()
+            .map(|c| match upper {
+                true => char::from(c.to_ascii_uppercase()),
+                false => char::from(*c),
+            })
+            .collect::<Vec<_>>()
+    };
+    let mut password = Vec::with_capacity(pattern.0.len());
+    for part in &pattern.0 {
+        let pool = match *part {
+            PatternPart::Literal(c) => {
+                password.push(c);
+                continue;
+            }
+            PatternPart::Consonant { upper } => letters(CONSONANTS, upper),
+            PatternPart::Vowel { upper } => letters(VOWELS, upper),
+            PatternPart::Letter { upper } => letters(LOWERCASE, upper),
+            PatternPart::Digit => letters(NUMBERS, false),
+            PatternPart::Symbol if symbols.is_empty() => {
+                return Err(anyhow::format_err!("No symbols are allowed"))
+            }
+            PatternPart::Symbol => symbols.to_vec(),
+        };
+        let c = pick(&password, &unambiguous(pool, options), options, rng)?;
+        password.push(c);
+    }
+    Ok(password.into_iter().collect())
+}
+
+fn unambiguous(pool: Vec<char>, options: &GenPassOptions) -> Vec<char> {
+    if !options.no_ambiguous {
+        return pool;
+    }
+    pool.into_iter()
+        .filter(|c| !c.is_ascii() || !AMBIGUOUS.contains(&(*c as u8)))
+        .collect()
+}
+
+/// a random character of `pool` that keeps the password within the rules
+///
+/// characters are picked one at a time so `--no-repeat` and `--no-sequence` only ever
+/// rule out a couple of candidates instead of rejecting whole passwords
+fn pick(
+    password: &[char],
+    pool: &[char],
+    options: &GenPassOptions,
+    rng: &mut impl Rng,
+) -> Result<char> {
+    let candidates = pool
+        .iter()
+        .filter(|c| allowed(password, **c, options))
+        .collect::<Vec<_>>();
+    candidates.choose(rng).map(|c| **c).ok_or_else(|| {
+        anyhow::format_err!("Too few characters to satisfy --no-repeat and --no-sequence")
+    })
+}
+
+/// whether `c` may follow the characters generated so far
+fn allowed(password: &[char], c: char, options: &GenPassOptions) -> bool {
+    match password {
+        [.., last] if options.no_repeat && *last == c => false,
+        // `abc`, `321` and the like
+        [.., a, b] if options.no_sequence => {
+            let (a, b, c) = (*a as i64, *b as i64, c as i64);
+            !((b - a).abs() == 1 && c - b == b - a)
+        }
+        _ => true,
     }
-    if !options.no_lowercase {
-        chars.extend_from_slice(b"abcdefghijklmnpqrstuvwxyz");
+}
+
+/// diceware style, every word adds about 12.9 bits of entropy
+fn passphrase(
+    count: usize,
+    options: &GenPassOptions,
+    symbols: &[char],
+    rng: &mut impl Rng,
+) -> Result<String> {
+    if count == 0 {
+        return Err(anyhow::format_err!("A passphrase needs at least one word"));
     }
-    if !options.no_numbers {
-        chars.extend_from_slice(b"0123456789");
+    let mut words = (0..count)
+        .map(|_| {
+            let word = WORDS.choose(rng).copied().unwrap_or_default();
+            if options.capitalize {
+                let mut chars = word.chars();
+                chars
+                    .next()
+                    .map(|first| first.to_ascii_uppercase().to_string() + chars.as_str())
+                    .unwrap_or_default()
+            } else {
+                word.to_string()
+            }
+        })
+        .collect::<Vec<_>>();
+    if options.digit {
+        let digit = char::from(b'0' + rng.gen_range(0..10));
+        let index = rng.gen_range(0..count);
+        words[index].push(digit);
     }
-    if !options.no_symbols {
-        chars.extend_from_slice(b"@#$%^&*?");
+    if options.symbol {
+        let symbol = symbols
+            .choose(rng)
+            .ok_or_else(|| anyhow::format_err!("No symbols are allowed"))?;
+        let index = rng.gen_range(0..count);
+        words[index].push(*symbol);
     }
-    for _ in 0..options.length {
-        let index = rng.gen_range(0..chars.len());
-        password.push(chars[index] as char);
+    Ok(words.join(&options.separator))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::gen_pass_options::Charset;
+
+    #[test]
+    fn test_passphrase() -> Result<()> {
+        assert_eq!(WORDLIST.lines().count(), 7776);
+        assert_eq!(WORDS.len(), 7776);
+        let options = GenPassOptions {
+            words: Some(6),
+            separator: " ".to_string(),
+            capitalize: true,
+            digit: true,
+            symbol: true,
+            ..Default::default()
+        };
+        let passphrase = process_genpass(&options)?;
+        let words = passphrase.split(' ').collect::<Vec<_>>();
+        assert_eq!(words.len(), 6);
+        assert!(words
+            .iter()
+            .all(|w| w.starts_with(|c: char| c.is_ascii_uppercase())));
+        assert!(passphrase.chars().any(|c| c.is_ascii_digit()));
+        assert!(passphrase.bytes().any(|b| SYMBOLS.contains(&b)));
+
+        let options = GenPassOptions {
+            words: Some(0),
+            ..Default::default()
+        };
+        assert!(process_genpass(&options).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_policy() -> Result<()> {
+        let options = GenPassOptions {
+            length: 8,
+            min_each: 2,
+            no_ambiguous: true,
+            ..Default::default()
+        };
+        for _ in 0..100 {
+            let password = process_genpass(&options)?;
+            let count = |class: &[u8]| password.bytes().filter(|c| class.contains(c)).count();
+            assert_eq!(
+                [UPPERCASE, LOWERCASE, NUMBERS, SYMBOLS].map(count),
+                [2, 2, 2, 2]
+            );
+            assert!(!password.bytes().any(|c| AMBIGUOUS.contains(&c)));
+        }
+
+        let options = GenPassOptions {
+            length: 500,
+            no_uppercase: true,
+            no_lowercase: true,
+            no_symbols: true,
+            no_repeat: true,
+            no_sequence: true,
+            ..Default::default()
+        };
+        let password = process_genpass(&options)?.chars().collect::<Vec<_>>();
+        let allowed_everywhere =
+            (0..password.len()).all(|i| allowed(&password[..i], password[i], &options));
+        assert!(allowed_everywhere);
+        assert!(!allowed(&['1', '2'], '3', &options));
+        assert!(!allowed(&['x', '9'], '9', &options));
+
+        let options = GenPassOptions {
+            no_uppercase: true,
+            no_lowercase: true,
+            no_numbers: true,
+            no_symbols: true,
+            ..Default::default()
+        };
+        assert!(process_genpass(&options).is_err());
+        let options = GenPassOptions {
+            length: 3,
+            ..Default::default()
+        };
+        assert!(process_genpass(&options).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_charset_and_pattern() -> Result<()> {
+        let options = GenPassOptions {
+            length: 64,
+            charset: Some("x-z_".parse()?),
+            ..Default::default()
+        };
+        let password = process_genpass(&options)?;
+        assert!(password.chars().all(|c| "xyz_".contains(c)));
+        let charset: Charset = r"_a-c-\-".parse()?;
+        assert_eq!(charset.0, vec!['_', 'a', 'b', 'c', '-']);
+        assert!("z-a".parse::<Charset>().is_err());
+
+        let options = GenPassOptions {
+            pattern: Some(r"Cvcc-9999-s\s".parse()?),
+            no_ambiguous: true,
+            ..Default::default()
+        };
+        let password = process_genpass(&options)?.chars().collect::<Vec<_>>();
+        assert_eq!(password.len(), 12);
+        assert!(CONSONANTS.contains(&(password[0].to_ascii_lowercase() as u8)));
+        assert!(password[0].is_ascii_uppercase());
+        assert!(VOWELS.contains(&(password[1] as u8)));
+        assert!(password[5..9]
+            .iter()
+            .all(|c| c.is_ascii_digit() && *c > '1'));
+        assert!(SYMBOLS.contains(&(password[10] as u8)));
+        assert_eq!((password[4], password[9], password[11]), ('-', '-', 's'));
+        Ok(())
+    }
+
+    #[test]
+    fn test_batch() -> Result<()> {
+        let options = GenPassOptions {
+            count: 10,
+            unique: true,
+            pattern: Some("9".parse()?),
+            ..Default::default()
+        };
+        let mut passwords = process_genpass_batch(&options)?;
+        passwords.sort();
+        assert_eq!(passwords.join(""), "0123456789");
+        let options = GenPassOptions {
+            count: 11,
+            ..options
+        };
+        assert!(process_genpass_batch(&options).is_err());
+
+        let passwords = vec!["password".to_string(), "q7#Vd!9zLm@2xR$e".to_string()];
+        let csv = process_genpass_report(&passwords, PassFormat::Csv)?;
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "password,score,entropy,crack_time");
+        assert!(lines[1].starts_with("password,0,"));
+        let json: serde_json::Value =
+            serde_json::from_str(&process_genpass_report(&passwords, PassFormat::Json)?)?;
+        assert_eq!(json[1]["score"], 4);
+        assert_eq!(
+            process_genpass_report(&passwords, PassFormat::Plain)?,
+            "password\nq7#Vd!9zLm@2xR$e\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_policy_file() -> Result<()> {
+        let options = GenPassOptions {
+            length: 8,
+            policy: Some("fixtures/password.policy.yaml".to_string()),
+            ..Default::default()
+        };
+        let policy = Policy::load("fixtures/password.policy.yaml")?;
+        let password = process_genpass(&options)?;
+        assert_eq!(password.len(), 14);
+        assert!(policy
+            .violations(&password, &process_strength(&password))
+            .is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_min_score() -> Result<()> {
+        let options = GenPassOptions {
+            min_score: Some(4),
+            ..Default::default()
+        };
+        assert!(process_strength(&process_genpass(&options)?).score >= 4);
+
+        let options = GenPassOptions {
+            length: 4,
+            min_score: Some(4),
+            ..Default::default()
+        };
+        assert!(process_genpass(&options).is_err());
+        Ok(())
     }
-    Ok(password)
 }
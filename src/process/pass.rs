@@ -0,0 +1,87 @@
+use anyhow::Result;
+use std::{fmt, io::Read};
+use zxcvbn::zxcvbn;
+
+use super::policy::Policy;
+use crate::utils::get_reader;
+
+/// zxcvbn's estimate of how hard a password is to guess
+#[derive(Debug, Clone, PartialEq)]
+pub struct Strength {
+    /// 0 (too guessable) to 4 (very unguessable)
+    pub score: u8,
+    /// against an offline attack on a slow hash, 10k guesses per second
+    pub crack_time: String,
+    /// log2 of the estimated number of guesses
+    pub entropy: f64,
+    pub warning: Option<String>,
+    pub suggestions: Vec<String>,
+}
+
+impl fmt::Display for Strength {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "score: {}/4", self.score)?;
+        writeln!(f, "crack time: {}", self.crack_time)?;
+        write!(f, "entropy: {:.1} bits", self.entropy)?;
+        if let Some(warning) = &self.warning {
+            write!(f, "\nwarning: {}", warning)?;
+        }
+        for suggestion in &self.suggestions {
+            write!(f, "\nsuggestion: {}", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn process_strength(password: &str) -> Strength {
+    let entropy = zxcvbn(password, &[]);
+    let feedback = entropy.feedback();
+    Strength {
+        score: entropy.score().into(),
+        crack_time: entropy
+            .crack_times()
+            .offline_slow_hashing_1e4_per_second()
+            .to_string(),
+        entropy: entropy.guesses_log10() * std::f64::consts::LOG2_10,
+        warning: feedback.and_then(|f| f.warning()).map(|w| w.to_string()),
+        suggestions: feedback
+            .map(|f| f.suggestions().iter().map(|s| s.to_string()).collect())
+            .unwrap_or_default(),
+    }
+}
+
+/// score the first line of the input, so `echo secret | rcli pass check` works,
+/// and list the rules of the policy it breaks
+pub fn process_pass_check(input: &str, policy: Option<&str>) -> Result<(Strength, Vec<String>)> {
+    let policy = policy.map(Policy::load).transpose()?;
+    let mut reader = get_reader(input)?;
+    let mut buf = String::new();
+    reader.read_to_string(&mut buf)?;
+    let password = buf.lines().next().unwrap_or_default();
+    if password.is_empty() {
+        return Err(anyhow::format_err!("No password to check"));
+    }
+    let strength = process_strength(password);
+    let violations = policy
+        .map(|p| p.violations(password, &strength))
+        .unwrap_or_default();
+    Ok((strength, violations))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strength() {
+        let weak = process_strength("password");
+        assert_eq!(weak.score, 0);
+        assert!(weak.warning.is_some());
+        assert!(weak.to_string().starts_with("score: 0/4\n"));
+
+        let strong = process_strength("q7#Vd!9zLm@2xR$e");
+        assert_eq!(strong.score, 4);
+        assert!(strong.entropy > 40.0);
+        assert_eq!(strong.crack_time, "centuries");
+    }
+}
@@ -0,0 +1,161 @@
+use anyhow::Result;
+use serde::Deserialize;
+use std::fs;
+
+use super::pass::Strength;
+
+/// password rules shared by `gen-pass --policy` and `pass check --policy`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    #[serde(default)]
+    pub min_length: Option<usize>,
+    #[serde(default)]
+    pub max_length: Option<usize>,
+    /// classes every password must contain at least one character of
+    #[serde(default)]
+    pub require: Vec<CharClass>,
+    /// the only symbols allowed, any symbol is allowed if not set
+    #[serde(default)]
+    pub symbols: Option<String>,
+    /// matched case-insensitively anywhere in the password
+    #[serde(default)]
+    pub banned: Vec<String>,
+    /// lowest zxcvbn score accepted, 0 to 4
+    #[serde(default)]
+    pub min_score: Option<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CharClass {
+    Uppercase,
+    Lowercase,
+    #[serde(alias = "numbers")]
+    Digits,
+    Symbols,
+}
+
+impl CharClass {
+    fn contains(&self, c: char) -> bool {
+        match self {
+            CharClass::Uppercase => c.is_uppercase(),
+            CharClass::Lowercase => c.is_lowercase(),
+            CharClass::Digits => c.is_ascii_digit(),
+            CharClass::Symbols => is_symbol(c),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            CharClass::Uppercase => "uppercase letters",
+            CharClass::Lowercase => "lowercase letters",
+            CharClass::Digits => "digits",
+            CharClass::Symbols => "symbols",
+        }
+    }
+}
+
+impl Policy {
+    pub fn load(path: &str) -> Result<Self> {
+        let policy: Policy = serde_yaml::from_str(&fs::read_to_string(path)?)?;
+        if let (Some(min), Some(max)) = (policy.min_length, policy.max_length) {
+            if min > max {
+                return Err(anyhow::format_err!(
+                    "Policy min_length {} is greater than max_length {}",
+                    min,
+                    max
+                ));
+            }
+        }
+        if policy.min_score.is_some_and(|score| score > 4) {
+            return Err(anyhow::format_err!(
+                "Policy min_score must be between 0 and 4"
+            ));
+        }
+        Ok(policy)
+    }
+
+    /// the closest length the policy accepts
+    pub fn clamp_length(&self, length: usize) -> usize {
+        let length = self.min_length.map_or(length, |min| length.max(min));
+        self.max_length.map_or(length, |max| length.min(max))
+    }
+
+    /// every rule the password breaks, empty if it passes
+    pub fn violations(&self, password: &str, strength: &Strength) -> Vec<String> {
+        let mut ret = Vec::new();
+        let length = password.chars().count();
+        if let Some(min) = self.min_length.filter(|min| length < *min) {
+            ret.push(format!("{} characters is shorter than {}", length, min));
+        }
+        if let Some(max) = self.max_length.filter(|max| length > *max) {
+            ret.push(format!("{} characters is longer than {}", length, max));
+        }
+        for class in &self.require {
+            if !password.chars().any(|c| class.contains(c)) {
+                ret.push(format!("has no {}", class.name()));
+            }
+        }
+        if let Some(symbols) = &self.symbols {
+            let mut rejected = String::new();
+            for c in password.chars() {
+                if is_symbol(c) && !symbols.contains(c) && !rejected.contains(c) {
+                    rejected.push(c);
+                }
+            }
+            if !rejected.is_empty() {
+                ret.push(format!("symbols {:?} are not allowed", rejected));
+            }
+        }
+        let lower = password.to_lowercase();
+        for banned in &self.banned {
+            if lower.contains(&banned.to_lowercase()) {
+                ret.push(format!("contains banned text {:?}", banned));
+            }
+        }
+        if let Some(min) = self.min_score.filter(|min| strength.score < *min) {
+            ret.push(format!("score {} is below {}", strength.score, min));
+        }
+        ret
+    }
+}
+
+fn is_symbol(c: char) -> bool {
+    !c.is_alphanumeric() && !c.is_whitespace()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::process_strength;
+
+    #[test]
+    fn test_violations() -> Result<()> {
+        let policy: Policy = serde_yaml::from_str(
+            "min_length: 10
+max_length: 20
+require: [uppercase, digits, symbols]
+symbols: '!@'
+banned: [acme]
+min_score: 3",
+        )?;
+        let check = |password: &str| policy.violations(password, &process_strength(password));
+        assert!(check("Tr0ub4dor!x@Zq").is_empty());
+        assert_eq!(
+            check("acmeacme#"),
+            vec![
+                "9 characters is shorter than 10",
+                "has no uppercase letters",
+                "has no digits",
+                "symbols \"#\" are not allowed",
+                "contains banned text \"acme\"",
+                "score 1 is below 3",
+            ]
+        );
+        assert_eq!(policy.clamp_length(16), 16);
+        assert_eq!(policy.clamp_length(4), 10);
+        assert!(serde_yaml::from_str::<Policy>("require: [emoji]").is_err());
+        Ok(())
+    }
+}
@@ -75,10 +75,7 @@ impl KeyGenerator for Blake3 {
     fn generate() -> Result<Vec<Vec<u8>>> {
         let options = GenPassOptions {
             length: 32,
-            no_uppercase: false,
-            no_lowercase: false,
-            no_numbers: false,
-            no_symbols: false,
+            ..Default::default()
         };
         let password = process_genpass(&options)?;
         Ok(vec![password.as_bytes().to_vec()])
@@ -1,6 +1,8 @@
+use chrono::format::{DelayedFormat, StrftimeItems};
 use std::{
+    fmt,
     fs::File,
-    io::{stdin, Read},
+    io::{stdin, stdout, BufWriter, Read, Write},
 };
 
 pub fn get_reader(input: &str) -> Result<Box<dyn Read>, anyhow::Error> {
@@ -10,3 +12,40 @@ pub fn get_reader(input: &str) -> Result<Box<dyn Read>, anyhow::Error> {
         Ok(Box::new(File::open(input)?))
     }
 }
+
+pub fn get_writer(output: &str) -> Result<Box<dyn Write>, anyhow::Error> {
+    if output == "-" {
+        Ok(Box::new(BufWriter::new(stdout())))
+    } else {
+        Ok(Box::new(BufWriter::new(File::create(output)?)))
+    }
+}
+
+/// render a chrono date formatted with a user supplied `format`
+pub fn format_date(date: DelayedFormat<StrftimeItems>, format: &str) -> anyhow::Result<String> {
+    // chrono reports bad format strings through fmt::Error, which would panic in to_string
+    let mut ret = String::new();
+    fmt::write(&mut ret, format_args!("{}", date))
+        .map_err(|_| anyhow::format_err!("Invalid date format: {}", format))?;
+    Ok(ret)
+}
+
+#[cfg(test)]
+pub(crate) mod testing {
+    use std::{cell::RefCell, io::Write, rc::Rc};
+
+    /// a cloneable in-memory writer so the output can be inspected after the writer is consumed
+    #[derive(Clone, Default)]
+    pub struct Sink(pub Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Sink {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+}